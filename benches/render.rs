@@ -0,0 +1,76 @@
+#![allow(clippy::needless_return)]
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use path_tracer::{
+    load_off::load_off,
+    mesh::{Mesh, Triangle},
+    render,
+    scenes::load_scenes,
+    Material, Ray, ReflectType, RenderConfig, SceneObject, SceneObjectData, Vector, Visibility,
+};
+
+fn object(type_: SceneObject) -> SceneObjectData {
+    return SceneObjectData {
+        type_,
+        position: Vector::from(0.0, 0.0, -3.0),
+        material: Material {
+            color: Vector::uniform(0.5),
+            emmission: Vector::zero(),
+            reflect_type: ReflectType::Diffuse,
+            vertex_colors: false,
+            texture: None,
+        },
+        visibility: Visibility::Visible,
+        name: None,
+    };
+}
+
+fn intersection(c: &mut Criterion) {
+    let ray = Ray {
+        origin: Vector::zero(),
+        direction: Vector::from(0.01, 0.02, -1.0).normalize(),
+    };
+
+    let sphere = object(SceneObject::Sphere { radius: 1.0 });
+    c.bench_function("intersect/sphere", |b| {
+        b.iter(|| black_box(&sphere).intersect(black_box(&ray)))
+    });
+
+    let triangle = object(SceneObject::Mesh(Mesh::from_triangles(&[Triangle {
+        a: Vector::from(-1.0, -1.0, 0.0),
+        b: Vector::from(1.0, -1.0, 0.0),
+        c: Vector::from(0.0, 1.0, 0.0),
+    }])));
+    c.bench_function("intersect/triangle", |b| {
+        b.iter(|| black_box(&triangle).intersect(black_box(&ray)))
+    });
+
+    let mesh = object(SceneObject::Mesh(
+        load_off("meshes/mctri.off", 0.16).unwrap(),
+    ));
+    c.bench_function("intersect/mesh", |b| {
+        b.iter(|| black_box(&mesh).intersect(black_box(&ray)))
+    });
+}
+
+fn scenes(c: &mut Criterion) {
+    c.bench_function("load_scenes", |b| b.iter(|| load_scenes().unwrap()));
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    let config = RenderConfig {
+        samples_per_pixel: 4,
+        resolution_y: 24,
+        quiet: true,
+        ..RenderConfig::default()
+    };
+    for scene in load_scenes().unwrap() {
+        group.bench_function(&scene.id, |b| b.iter(|| render(&scene, &config)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, intersection, scenes);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+use crate::{SceneData, Vector};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub value: Vector,
+    /// How the value moves from the previous keyframe to this one
+    pub easing: Easing,
+}
+
+/// Timing of the interpolation between two keyframes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts at rest and speeds up
+    EaseIn,
+    /// Slows down to rest at the keyframe
+    EaseOut,
+    /// Starts and stops at rest, for camera moves without jolts
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the linear fraction `t` in [0, 1] of the time between two
+    /// keyframes to the fraction of the way between their values.
+    pub fn apply(&self, t: f64) -> f64 {
+        return match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        };
+    }
+}
+
+/// Keyframed camera and object transforms. Values between keyframes are
+/// interpolated with the easing of the later keyframe and held constant
+/// before the first and after the last keyframe.
+#[derive(Clone, Debug, Default)]
+pub struct Animation {
+    pub camera_position: Vec<Keyframe>,
+    pub camera_direction: Vec<Keyframe>,
+    /// Position keyframes by index into `SceneData.objects`
+    pub object_positions: Vec<(usize, Vec<Keyframe>)>,
+}
+
+impl Animation {
+    /// First and last frame that has a keyframe.
+    pub fn frame_range(&self) -> Option<(usize, usize)> {
+        let frames = self
+            .camera_position
+            .iter()
+            .chain(self.camera_direction.iter())
+            .chain(self.object_positions.iter().flat_map(|(_, k)| k.iter()))
+            .map(|keyframe| keyframe.frame);
+        return frames.clone().min().zip(frames.max());
+    }
+
+    /// Returns a copy of `scene` with all animated values set for `frame`.
+    pub fn evaluate(&self, scene: &SceneData, frame: usize) -> SceneData {
+        let mut result = scene.clone();
+        if let Some(position) = interpolate(&self.camera_position, frame) {
+            result.camera.position = position;
+        }
+        if let Some(direction) = interpolate(&self.camera_direction, frame) {
+            result.camera.direction = direction.normalize();
+        }
+        for (object_index, keyframes) in self.object_positions.iter() {
+            if let Some(position) = interpolate(keyframes, frame) {
+                result.objects[*object_index].position = position;
+            }
+        }
+        return result;
+    }
+}
+
+/// Interpolation between the keyframes surrounding `frame`. `keyframes`
+/// must be sorted by frame.
+fn interpolate(keyframes: &[Keyframe], frame: usize) -> Option<Vector> {
+    let next = keyframes.iter().position(|k| k.frame >= frame);
+    return match next {
+        None => keyframes.last().map(|k| k.value),
+        Some(0) => Some(keyframes[0].value),
+        Some(i) => {
+            let (a, b) = (keyframes[i - 1], keyframes[i]);
+            let t = (frame - a.frame) as f64 / (b.frame - a.frame) as f64;
+            let t = b.easing.apply(t);
+            Some(a.value * (1.0 - t) + b.value * t)
+        }
+    };
+}
@@ -0,0 +1,71 @@
+use crate::Vector;
+
+// Colors are linear everywhere in the renderer: in scenes, materials, textures
+// and the rendered radiance. sRGB values, like 8 bit colors from files or
+// color pickers, are converted with `srgb_to_linear` when they are loaded.
+// The output image is made displayable by `Tonemap` and then encoded by
+// `ColorEncoding`, both once per pixel in `RenderConfig::display`.
+
+/// Transfer function of the values written to the output image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorEncoding {
+    /// The piecewise sRGB curve that displays expect
+    Srgb,
+    /// Plain 1 / 2.2 power, like older versions of this renderer
+    Gamma22,
+    /// No encoding, for images that are processed further
+    Linear,
+}
+
+impl ColorEncoding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "srgb" => Some(ColorEncoding::Srgb),
+            "gamma2.2" => Some(ColorEncoding::Gamma22),
+            "linear" => Some(ColorEncoding::Linear),
+            _ => None,
+        };
+    }
+
+    /// Encodes a linear value in [0, 1].
+    pub fn encode(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        return match self {
+            ColorEncoding::Srgb => linear_to_srgb(x),
+            ColorEncoding::Gamma22 => x.powf(1.0 / 2.2),
+            ColorEncoding::Linear => x,
+        };
+    }
+}
+
+pub fn srgb_to_linear(x: f64) -> f64 {
+    return if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    };
+}
+
+pub fn linear_to_srgb(x: f64) -> f64 {
+    return if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    };
+}
+
+/// Parses a linear color like `0.5,0.2,0.1`, or an sRGB color in hex
+/// notation like `#ff8000`, which is converted to linear.
+pub fn parse_color(s: &str) -> Option<Vector> {
+    let Some(hex) = s.trim().strip_prefix('#') else {
+        return Vector::parse(s);
+    };
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| -> Option<f64> {
+        let value = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+        return Some(srgb_to_linear(value as f64 / 255.0));
+    };
+    return Some(Vector::from(channel(0)?, channel(1)?, channel(2)?));
+}
@@ -0,0 +1,214 @@
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use crate::{sampler::AliasTable, Vector};
+
+/// Equirectangular environment image with a sampling distribution
+/// proportional to texel luminance. +y is up, the image center looks
+/// towards -z.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector>,
+    /// Picks texels by `texel_weight`, in constant time even for large maps
+    texels: AliasTable,
+    /// Sum of all texel weights
+    total_weight: f64,
+}
+
+/// Luminance of a texel in `row` times the solid angle it covers, up to a
+/// constant factor: rows near the poles cover less
+fn texel_weight(texel: Vector, row: usize, height: usize) -> f64 {
+    let sin_theta = (PI * (row as f64 + 0.5) / height as f64).sin();
+    return (0.2126 * texel.x + 0.7152 * texel.y + 0.0722 * texel.z) * sin_theta;
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector>) -> Self {
+        let weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, texel)| texel_weight(*texel, i / width, height))
+            .collect();
+        return EnvironmentMap {
+            width,
+            height,
+            pixels,
+            texels: AliasTable::new(&weights),
+            total_weight: weights.iter().sum(),
+        };
+    }
+
+    fn texel(&self, direction: Vector) -> (usize, usize) {
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        return (
+            ((u * self.width as f64) as usize).min(self.width - 1),
+            ((v * self.height as f64) as usize).min(self.height - 1),
+        );
+    }
+
+    pub fn radiance(&self, direction: Vector) -> Vector {
+        let (x, y) = self.texel(direction);
+        return self.pixels[y * self.width + x];
+    }
+
+    /// Picks a direction with probability proportional to the environment's
+    /// luminance. Returns the direction and its pdf with respect to solid angle.
+    pub fn sample(&self, r1: f64, r2: f64) -> (Vector, f64) {
+        let (texel, r) = self.texels.sample(r1);
+
+        // Uniform position within the texel
+        let u = (texel % self.width) as f64 + r;
+        let v = (texel / self.width) as f64 + r2;
+        let theta = v / self.height as f64 * PI;
+        let phi = (u / self.width as f64 - 0.5) * 2.0 * PI;
+        let direction = Vector::from(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        return (direction, self.pdf(direction));
+    }
+
+    /// Solid angle density of `sample` producing `direction`.
+    pub fn pdf(&self, direction: Vector) -> f64 {
+        let (col, row) = self.texel(direction);
+        let sin_theta = (1.0 - direction.y.powi(2)).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let texel_count = (self.width * self.height) as f64;
+        let probability = if self.total_weight > 0.0 {
+            let texel = self.pixels[row * self.width + col];
+            texel_weight(texel, row, self.height) / self.total_weight
+        } else {
+            1.0 / texel_count
+        };
+        // Each texel covers (2π / width) * (π / height) * sin(θ) steradians
+        return probability * texel_count / (2.0 * PI * PI * sin_theta);
+    }
+}
+
+/// Loads an equirectangular Radiance .hdr (RGBE) image, flat or run-length encoded.
+pub fn load_hdr(path: &str, intensity: f64) -> Result<EnvironmentMap, std::io::Error> {
+    return parse_hdr(BufReader::new(File::open(path)?), intensity);
+}
+
+/// Reads a .hdr image from `reader`, see `load_hdr`.
+pub fn parse_hdr(
+    mut reader: impl BufRead,
+    intensity: f64,
+) -> Result<EnvironmentMap, std::io::Error> {
+    let bad_data =
+        |reason: &str| Result::Err(std::io::Error::new(std::io::ErrorKind::InvalidData, reason));
+
+    // Header lines until an empty line, then the resolution
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return bad_data("Invalid header");
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return bad_data("Missing resolution");
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            break;
+        }
+        if trimmed.starts_with("FORMAT=") && trimmed != "FORMAT=32-bit_rle_rgbe" {
+            return bad_data("Unsupported pixel format");
+        }
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (height, width): (usize, usize) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => match (height.parse(), width.parse()) {
+            (Ok(height), Ok(width)) => (height, width),
+            _ => return bad_data("Invalid resolution"),
+        },
+        _ => return bad_data("Unsupported image orientation"),
+    };
+
+    if width == 0 || height == 0 {
+        return bad_data("Empty image");
+    }
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    // A run stores at most 127 values of a channel in 2 bytes, so check the
+    // size against the data before allocating for it
+    match width.checked_mul(height) {
+        Some(texel_count) if texel_count / 127 * 8 <= data.len() => (),
+        _ => return bad_data("Resolution too large for the file"),
+    }
+    let mut pos = 0;
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        if data.len() < pos + 4 {
+            return bad_data("Unexpected end of file");
+        }
+        let is_rle = (8..0x8000).contains(&width)
+            && data[pos] == 2
+            && data[pos + 1] == 2
+            && ((data[pos + 2] as usize) << 8 | data[pos + 3] as usize) == width;
+        if is_rle {
+            pos += 4;
+            // Each channel is stored separately as runs and literals
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = *data.get(pos).ok_or(std::io::ErrorKind::UnexpectedEof)? as usize;
+                    pos += 1;
+                    if count > 128 {
+                        let count = count - 128;
+                        let value = *data.get(pos).ok_or(std::io::ErrorKind::UnexpectedEof)?;
+                        pos += 1;
+                        if x + count > width {
+                            return bad_data("Invalid run length");
+                        }
+                        for texel in scanline[x..x + count].iter_mut() {
+                            texel[channel] = value;
+                        }
+                        x += count;
+                    } else {
+                        if count == 0 || x + count > width || pos + count > data.len() {
+                            return bad_data("Invalid run length");
+                        }
+                        for (i, texel) in scanline[x..x + count].iter_mut().enumerate() {
+                            texel[channel] = data[pos + i];
+                        }
+                        pos += count;
+                        x += count;
+                    }
+                }
+            }
+        } else {
+            if data.len() < pos + width * 4 {
+                return bad_data("Unexpected end of file");
+            }
+            for (i, texel) in scanline.iter_mut().enumerate() {
+                texel.copy_from_slice(&data[pos + i * 4..pos + i * 4 + 4]);
+            }
+            pos += width * 4;
+        }
+
+        for [r, g, b, e] in scanline.iter() {
+            let scale = if *e == 0 {
+                0.0
+            } else {
+                2f64.powi(*e as i32 - 136) * intensity
+            };
+            pixels.push(Vector::from(*r as f64, *g as f64, *b as f64) * scale);
+        }
+    }
+
+    return Ok(EnvironmentMap::new(width, height, pixels));
+}
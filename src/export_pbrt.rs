@@ -0,0 +1,249 @@
+use std::fmt::Write;
+
+use crate::{
+    filter::FilterKind, light::LightShape, Environment, Material, ReflectType, RenderConfig,
+    SceneData, SceneObject, Vector, Visibility, MAX_DEPTH, SENSOR_WIDTH,
+};
+
+fn rgb(v: Vector) -> String {
+    format!("[{} {} {}]", v.x, v.y, v.z)
+}
+
+fn material(material: &Material) -> String {
+    match material.reflect_type {
+        ReflectType::Diffuse => format!("Material \"matte\" \"rgb Kd\" {}", rgb(material.color)),
+        ReflectType::Specular => format!("Material \"mirror\" \"rgb Kr\" {}", rgb(material.color)),
+        // pbrt-v3 glass has no absorption, so it is not exported
+        ReflectType::Refract { ior, .. } => format!(
+            "Material \"glass\" \"rgb Kr\" {0} \"rgb Kt\" {0} \"float eta\" {1}",
+            rgb(material.color),
+            ior
+        ),
+    }
+}
+
+/// Rotation that maps the +z axis onto `normal`, as pbrt `Rotate` arguments.
+fn rotate_z_to(normal: Vector) -> String {
+    let normal = normal.normalize();
+    let z = Vector::from(0.0, 0.0, 1.0);
+    let axis = z.cross(&normal);
+    if axis.magnitude() < 1e-9 {
+        // Already aligned or exactly opposite
+        return if normal.z > 0.0 {
+            "".to_owned()
+        } else {
+            "Rotate 180 1 0 0\n".to_owned()
+        };
+    }
+    let angle = z.dot(&normal).clamp(-1.0, 1.0).acos().to_degrees();
+    let axis = axis.normalize();
+    format!("Rotate {} {} {} {}\n", angle, axis.x, axis.y, axis.z)
+}
+
+/// Exports `scene` as a pbrt-v3 scene description, so it can be rendered by
+/// a reference renderer and compared against our output.
+pub fn export_pbrt(scene: &SceneData, render_config: &RenderConfig, filename: &str) -> String {
+    let camera = &scene.camera;
+    let direction = camera.direction.normalize();
+    let eye = camera.position + direction * camera.focal_length;
+    let look_at = eye + direction;
+    let up = if direction.y.abs() < 0.9 {
+        Vector::from(0.0, 1.0, 0.0)
+    } else {
+        Vector::from(0.0, 0.0, 1.0)
+    };
+    // pbrt's fov spans the shorter image axis
+    let sensor_height =
+        SENSOR_WIDTH * render_config.resolution_y as f64 / render_config.resolution_x() as f64;
+    let fov = 2.0
+        * (sensor_height.min(SENSOR_WIDTH) / 2.0 / camera.focal_length)
+            .atan()
+            .to_degrees();
+
+    let mut out = String::new();
+    // pbrt uses a left-handed coordinate system
+    writeln!(out, "Scale -1 1 1").unwrap();
+    writeln!(
+        out,
+        "LookAt {} {} {}  {} {} {}  {} {} {}",
+        eye.x, eye.y, eye.z, look_at.x, look_at.y, look_at.z, up.x, up.y, up.z
+    )
+    .unwrap();
+    writeln!(out, "Camera \"perspective\" \"float fov\" {}", fov).unwrap();
+    writeln!(
+        out,
+        "Film \"image\" \"integer xresolution\" {} \"integer yresolution\" {} \"string filename\" \"{}\"",
+        render_config.resolution_x(),
+        render_config.resolution_y,
+        filename
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "Sampler \"random\" \"integer pixelsamples\" {}",
+        render_config.samples_per_pixel
+    )
+    .unwrap();
+    let radius = render_config.filter.radius;
+    let filter = match render_config.filter.kind {
+        FilterKind::Box => format!("PixelFilter \"box\" \"float xwidth\" {0} \"float ywidth\" {0}", radius),
+        FilterKind::Tent => format!("PixelFilter \"triangle\" \"float xwidth\" {0} \"float ywidth\" {0}", radius),
+        FilterKind::Gaussian => format!(
+            "PixelFilter \"gaussian\" \"float xwidth\" {0} \"float ywidth\" {0} \"float alpha\" {1}",
+            radius,
+            4.5 / (radius * radius)
+        ),
+        FilterKind::BlackmanHarris => "# pbrt-v3 has no Blackman-Harris filter".to_owned(),
+    };
+    writeln!(out, "{}", filter).unwrap();
+    writeln!(
+        out,
+        "Integrator \"path\" \"integer maxdepth\" {}",
+        scene.render_settings.max_depth.unwrap_or(MAX_DEPTH)
+    )
+    .unwrap();
+    writeln!(out, "WorldBegin").unwrap();
+    match scene.environment {
+        Environment::Black => (),
+        Environment::Color(color) => {
+            writeln!(out, "LightSource \"infinite\" \"rgb L\" {}", rgb(color)).unwrap()
+        }
+        _ => writeln!(out, "# Environment lighting is not exported").unwrap(),
+    }
+    if scene.medium.is_some() {
+        writeln!(out, "# The participating medium is not exported").unwrap();
+    }
+
+    for object in scene.objects.iter() {
+        match object.visibility {
+            Visibility::Visible => (),
+            Visibility::CameraInvisible => writeln!(
+                out,
+                "# Exported as visible, pbrt-v3 has no camera visibility"
+            )
+            .unwrap(),
+            Visibility::Hidden => continue,
+        }
+        writeln!(out, "AttributeBegin").unwrap();
+        let p = object.position;
+        writeln!(out, "Translate {} {} {}", p.x, p.y, p.z).unwrap();
+        writeln!(out, "{}", material(&object.material)).unwrap();
+        let emission = object.material.emmission;
+        if emission != Vector::zero() {
+            // Our emitters emit from both sides, except closed spheres
+            let two_sided = !matches!(object.type_, SceneObject::Sphere { .. });
+            writeln!(
+                out,
+                "AreaLightSource \"diffuse\" \"rgb L\" {} \"bool twosided\" \"{}\"",
+                rgb(emission),
+                two_sided
+            )
+            .unwrap();
+        }
+
+        match &object.type_ {
+            SceneObject::Sphere { radius } => {
+                writeln!(out, "Shape \"sphere\" \"float radius\" {}", radius).unwrap();
+            }
+            SceneObject::Plane { normal } => {
+                // pbrt has no infinite plane, approximate it with a large quad
+                let size = 1e5;
+                write!(out, "{}", rotate_z_to(*normal)).unwrap();
+                writeln!(
+                    out,
+                    "Shape \"trianglemesh\" \"integer indices\" [0 1 2 0 2 3] \"point P\" [-{0} -{0} 0 {0} -{0} 0 {0} {0} 0 -{0} {0} 0]",
+                    size
+                )
+                .unwrap();
+            }
+            SceneObject::Disc { normal, radius } => {
+                write!(out, "{}", rotate_z_to(*normal)).unwrap();
+                writeln!(out, "Shape \"disk\" \"float radius\" {}", radius).unwrap();
+            }
+            SceneObject::Mesh(mesh) => {
+                let mut points = String::new();
+                let mut indices = String::new();
+                for i in 0..mesh.triangle_count() {
+                    let tri = mesh.triangle(i);
+                    for (j, vert) in [tri.a, tri.b, tri.c].iter().enumerate() {
+                        write!(points, "{} {} {} ", vert.x, vert.y, vert.z).unwrap();
+                        write!(indices, "{} ", i * 3 + j).unwrap();
+                    }
+                }
+                writeln!(
+                    out,
+                    "Shape \"trianglemesh\" \"integer indices\" [{}] \"point P\" [{}]",
+                    indices.trim_end(),
+                    points.trim_end()
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "AttributeEnd").unwrap();
+    }
+
+    for light in &scene.lights {
+        let p = light.position;
+        match light.shape {
+            LightShape::Point => writeln!(
+                out,
+                "LightSource \"point\" \"rgb I\" {} \"point from\" [{} {} {}]",
+                rgb(light.emission),
+                p.x,
+                p.y,
+                p.z
+            )
+            .unwrap(),
+            LightShape::Spot {
+                direction,
+                cone_angle,
+                falloff,
+            } => {
+                let to = p + direction;
+                writeln!(
+                    out,
+                    "LightSource \"spot\" \"rgb I\" {} \"point from\" [{} {} {}] \"point to\" [{} {} {}] \"float coneangle\" {} \"float conedelta\" {}",
+                    rgb(light.emission),
+                    p.x,
+                    p.y,
+                    p.z,
+                    to.x,
+                    to.y,
+                    to.z,
+                    cone_angle,
+                    falloff
+                )
+                .unwrap();
+            }
+            LightShape::Area { u, v } => {
+                writeln!(out, "AttributeBegin").unwrap();
+                writeln!(
+                    out,
+                    "AreaLightSource \"diffuse\" \"rgb L\" {}",
+                    rgb(light.emission)
+                )
+                .unwrap();
+                let corners = [
+                    p - u * 0.5 - v * 0.5,
+                    p + u * 0.5 - v * 0.5,
+                    p + u * 0.5 + v * 0.5,
+                    p - u * 0.5 + v * 0.5,
+                ];
+                let points: Vec<String> = corners
+                    .iter()
+                    .map(|c| format!("{} {} {}", c.x, c.y, c.z))
+                    .collect();
+                writeln!(
+                    out,
+                    "Shape \"trianglemesh\" \"integer indices\" [0 1 2 0 2 3] \"point P\" [{}]",
+                    points.join(" ")
+                )
+                .unwrap();
+                writeln!(out, "AttributeEnd").unwrap();
+            }
+        }
+    }
+
+    writeln!(out, "WorldEnd").unwrap();
+    return out;
+}
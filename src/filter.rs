@@ -0,0 +1,121 @@
+use std::f64::consts::PI;
+
+/// Shape of the pixel reconstruction filter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted
+    /// to reach zero at the radius
+    Gaussian,
+    BlackmanHarris,
+}
+
+/// Weights samples around each pixel center by their offset. Filters are
+/// separable, the weight of a sample is that of its x times its y offset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// In pixels. Filters wider than half a pixel take samples from the
+    /// neighboring pixels too.
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        return Filter {
+            kind: FilterKind::Tent,
+            radius: 1.0,
+        };
+    }
+}
+
+impl Filter {
+    /// Parses `box`, `tent`, `gaussian` or `blackman-harris`, optionally
+    /// followed by `:<radius>`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (kind, radius) = match name.split_once(':') {
+            Some((kind, radius)) => (kind, Some(radius.parse::<f64>().ok()?)),
+            None => (name, None),
+        };
+        let (kind, default_radius) = match kind {
+            "box" => (FilterKind::Box, 0.5),
+            "tent" => (FilterKind::Tent, 1.0),
+            "gaussian" => (FilterKind::Gaussian, 1.5),
+            "blackman-harris" => (FilterKind::BlackmanHarris, 2.0),
+            _ => return None,
+        };
+        let radius = radius.unwrap_or(default_radius);
+        if !(radius > 0.0 && radius.is_finite()) {
+            return None;
+        }
+        return Some(Filter { kind, radius });
+    }
+
+    /// Unnormalized weight of a sample `x` pixels from the pixel center
+    /// along one axis
+    pub fn weight(&self, x: f64) -> f64 {
+        let r = self.radius;
+        if x.abs() > r {
+            return 0.0;
+        }
+        return match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x.abs() / r,
+            FilterKind::Gaussian => {
+                let gaussian = |x: f64| (-4.5 * (x / r).powi(2)).exp();
+                gaussian(x) - gaussian(r)
+            }
+            FilterKind::BlackmanHarris => {
+                let t = 2.0 * PI * (x + r) / (2.0 * r);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        };
+    }
+}
+
+/// Draws sample offsets distributed like a filter. Every sample then has
+/// the same weight, so pixels can be rendered independently, without
+/// splatting samples into their neighbors.
+pub struct FilterSampler {
+    radius: f64,
+    /// Normalized cumulative weights at the edges of equally wide bins
+    /// spanning the filter
+    cdf: Vec<f64>,
+}
+
+/// Bins of the tabulated filter, fine enough that the error of
+/// interpolating within them is invisible
+const FILTER_BINS: usize = 256;
+
+impl FilterSampler {
+    pub fn new(filter: &Filter) -> Self {
+        let width = 2.0 * filter.radius / FILTER_BINS as f64;
+        let mut cdf = vec![0.0];
+        for i in 0..FILTER_BINS {
+            // Midpoint rule, exact for the box and tent filters
+            let x = -filter.radius + (i as f64 + 0.5) * width;
+            cdf.push(cdf[i] + filter.weight(x).max(0.0));
+        }
+        let total = cdf[FILTER_BINS];
+        for value in &mut cdf {
+            *value /= total;
+        }
+        return FilterSampler {
+            radius: filter.radius,
+            cdf,
+        };
+    }
+
+    /// Offset from the pixel center along one axis for `u` in [0, 1)
+    pub fn sample(&self, u: f64) -> f64 {
+        let bin = (self.cdf.partition_point(|&c| c <= u) - 1).min(FILTER_BINS - 1);
+        let (low, high) = (self.cdf[bin], self.cdf[bin + 1]);
+        let within = if high > low {
+            (u - low) / (high - low)
+        } else {
+            0.5
+        };
+        return -self.radius + 2.0 * self.radius * (bin as f64 + within) / FILTER_BINS as f64;
+    }
+}
@@ -0,0 +1,104 @@
+use std::f64::consts::PI;
+
+use crate::{
+    intersect_camera_ray, intersect_scene, radiance, rand01, Ray, SceneData, SceneIntersectResult,
+    Vector,
+};
+
+/// How the color of a camera ray is computed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Full global illumination with materials and lights
+    PathTracing,
+    /// White where the surface is unoccluded within `radius`, darker in
+    /// creases and contact areas
+    AmbientOcclusion { radius: f64 },
+    /// Uniform grey material lit from the camera, for quick geometry checks
+    Clay,
+    /// Path tracing with caustics from a photon map of `photons` photons,
+    /// averaged within `radius`
+    PhotonMapping { photons: usize, radius: f64 },
+}
+
+impl Integrator {
+    /// Parses `path`, `clay`, `ao`, `ao:<radius>`, `photon`, `photon:<radius>`
+    /// or `photon:<radius>:<photons>`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.split_once(':') {
+            None => match name {
+                "path" => Some(Integrator::PathTracing),
+                "ao" => Some(Integrator::AmbientOcclusion { radius: 1.0 }),
+                "clay" => Some(Integrator::Clay),
+                "photon" => Some(Integrator::PhotonMapping {
+                    photons: 1_000_000,
+                    radius: 0.05,
+                }),
+                _ => None,
+            },
+            Some(("ao", radius)) => Some(Integrator::AmbientOcclusion {
+                radius: radius.parse().ok().filter(|r: &f64| *r > 0.0)?,
+            }),
+            Some(("photon", params)) => {
+                let (radius, photons) = match params.split_once(':') {
+                    Some((radius, photons)) => (radius, photons.parse().ok()?),
+                    None => (params, 1_000_000),
+                };
+                Some(Integrator::PhotonMapping {
+                    photons,
+                    radius: radius.parse().ok().filter(|r: &f64| *r > 0.0)?,
+                })
+            }
+            Some(_) => None,
+        };
+    }
+
+    /// Color seen along the camera ray `ray`.
+    pub fn trace(&self, ray: &Ray, scene: &SceneData) -> Vector {
+        return match self {
+            // The photon map is part of the scene, see `SceneData::with_photon_map`
+            Integrator::PathTracing | Integrator::PhotonMapping { .. } => radiance(ray, 0, scene),
+            Integrator::AmbientOcclusion { radius } => ambient_occlusion(ray, scene, *radius),
+            Integrator::Clay => clay(ray, scene),
+        };
+    }
+}
+
+fn ambient_occlusion(ray: &Ray, scene: &SceneData, radius: f64) -> Vector {
+    let hit = match intersect_camera_ray(ray, scene) {
+        SceneIntersectResult::NoHit => return Vector::uniform(1.0),
+        SceneIntersectResult::Hit { hit, .. } => hit,
+    };
+    let w = if hit.normal.dot(&ray.direction) < 0.0 {
+        hit.normal
+    } else {
+        hit.normal * -1.0
+    };
+
+    // One cosine-weighted occlusion ray per sample
+    let r1 = 2.0 * PI * rand01();
+    let r2: f64 = rand01();
+    let u = (if w.x.abs() > 0.1 {
+        Vector::from(0.0, 1.0, 0.0)
+    } else {
+        Vector::from(1.0, 0.0, 0.0)
+    })
+    .cross(&w)
+    .normalize();
+    let v = w.cross(&u);
+    let direction =
+        (u * r1.cos() * r2.sqrt() + v * r1.sin() * r2.sqrt() + w * (1.0 - r2).sqrt()).normalize();
+    return match intersect_scene(&hit.spawn_ray(direction), &scene.objects, false) {
+        SceneIntersectResult::Hit { hit, .. } if hit.distance < radius => Vector::zero(),
+        _ => Vector::uniform(1.0),
+    };
+}
+
+fn clay(ray: &Ray, scene: &SceneData) -> Vector {
+    return match intersect_camera_ray(ray, scene) {
+        SceneIntersectResult::NoHit => Vector::zero(),
+        SceneIntersectResult::Hit { hit, .. } => {
+            let facing = hit.normal.dot(&ray.direction).abs();
+            Vector::uniform(0.8 * (0.2 + 0.8 * facing))
+        }
+    };
+}
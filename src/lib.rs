@@ -0,0 +1,2603 @@
+#![allow(clippy::needless_return)]
+
+//! Path tracing renderer with builtin scenes and mesh loading.
+
+pub mod animation;
+pub mod color;
+pub mod env_map;
+pub mod export_pbrt;
+pub mod filter;
+mod hash;
+pub mod integrator;
+pub mod light;
+pub mod load_off;
+pub mod load_ply;
+pub mod load_stl;
+pub mod medium;
+pub mod mesh;
+pub mod mesh_file;
+pub mod network;
+pub mod photon_map;
+pub mod primitives;
+pub mod sampler;
+pub mod scene_graph;
+pub mod scenes;
+pub mod sky;
+pub mod texture;
+pub mod tonemap;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(test)]
+mod test;
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    f64::consts::PI,
+    fmt::Display,
+    io::Write,
+    ops::{Add, Div, Mul, Range, Sub},
+    sync::{atomic, Arc},
+    time::Duration,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+// std's Instant panics in the browser
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use animation::Animation;
+use color::{parse_color, ColorEncoding};
+use env_map::EnvironmentMap;
+use filter::{Filter, FilterSampler};
+use integrator::Integrator;
+use light::Light;
+use medium::Medium;
+use mesh::Mesh;
+use photon_map::PhotonMap;
+use primitives::Primitive;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use sky::Sky;
+use texture::Texture;
+use tonemap::Tonemap;
+use tracing::info;
+
+const USE_CULLING: bool = false;
+/// Hits closer than this fraction of the scene scale around the ray origin
+/// are ignored. New rays are moved off their surface by `RAY_OFFSET`, so this
+/// only needs to catch what rounding leaves over.
+const HIT_EPSILON: f64 = 1e-9;
+/// Distance, as a fraction of the coordinate magnitude, by which new rays are
+/// moved off the surface they start on
+const RAY_OFFSET: f64 = 1e-8;
+/// in meters
+const SENSOR_WIDTH: f64 = 0.036;
+
+/// If true, render with a fixed sequence of random numbers.
+pub const MOCK_RANDOM: bool = false;
+#[allow(clippy::excessive_precision)]
+const MOCK_RANDOMS: [f64; 9] = [
+    0.75902418061906407,
+    0.023879213030728041,
+    0.21016190197770457,
+    0.78814922184253244,
+    0.56819568237964491,
+    0.7689823904006352,
+    0.16910304067812287,
+    0.54519597695203492,
+    0.63614169009490062,
+];
+const MOCK_RANDOMS_LEN: usize = MOCK_RANDOMS.len();
+static MOCK_RANDOMS_INDEX: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+thread_local! {
+    /// Generator used by `rand01` instead of the system's while rendering with a seed
+    static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Makes `rand01` on this thread deterministic, or random again for `None`.
+fn seed_rand01(seed: Option<u64>) {
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = seed.map(StdRng::seed_from_u64));
+}
+
+// uniform double random generator function
+fn rand01() -> f64 {
+    if MOCK_RANDOM {
+        let i = MOCK_RANDOMS_INDEX.fetch_add(1, atomic::Ordering::Relaxed) % MOCK_RANDOMS_LEN;
+        return MOCK_RANDOMS[i];
+    } else {
+        return SEEDED_RNG.with(|rng| match rng.borrow_mut().as_mut() {
+            Some(rng) => rng.gen::<f64>(),
+            None => rand::random::<f64>(),
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vector {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Add<Self> for Vector {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        return Vector {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        };
+    }
+}
+
+impl Sub<Self> for Vector {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self::Output {
+        return Vector {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        };
+    }
+}
+
+impl Mul<f64> for Vector {
+    type Output = Self;
+
+    fn mul(self, v: f64) -> Self::Output {
+        return Vector {
+            x: self.x * v,
+            y: self.y * v,
+            z: self.z * v,
+        };
+    }
+}
+
+impl Div<f64> for Vector {
+    type Output = Self;
+
+    fn div(self, v: f64) -> Self::Output {
+        return Vector {
+            x: self.x / v,
+            y: self.y / v,
+            z: self.z / v,
+        };
+    }
+}
+
+impl Mul<Self> for Vector {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        return Vector {
+            x: self.x * other.x,
+            y: self.y * other.y,
+            z: self.z * other.z,
+        };
+    }
+}
+
+impl Vector {
+    pub fn zero() -> Self {
+        Vector {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    pub const fn from(a: f64, b: f64, c: f64) -> Self {
+        Vector { x: a, y: b, z: c }
+    }
+
+    pub const fn uniform(u: f64) -> Self {
+        Vector { x: u, y: u, z: u }
+    }
+
+    pub fn normalize(mut self) -> Self {
+        let m = self.magnitude();
+        self.x /= m;
+        self.y /= m;
+        self.z /= m;
+        return self;
+    }
+
+    pub fn dot(&self, other: &Vector) -> f64 {
+        return self.x * other.x + self.y * other.y + self.z * other.z;
+    }
+
+    pub fn cross(&self, other: &Vector) -> Vector {
+        return Vector {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        };
+    }
+
+    pub fn magnitude(&self) -> f64 {
+        return (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt();
+    }
+
+    /// Largest absolute value of the three components
+    pub fn max_abs(&self) -> f64 {
+        return self.x.abs().max(self.y.abs()).max(self.z.abs());
+    }
+
+    /// Component along axis 0 (x), 1 (y) or 2 (z)
+    pub fn axis(&self, i: usize) -> f64 {
+        return [self.x, self.y, self.z][i];
+    }
+
+    /// Rotates counterclockwise around the y axis, looking down from +y.
+    pub fn rotate_y(&self, angle: f64) -> Vector {
+        let (sin, cos) = angle.sin_cos();
+        return Vector {
+            x: self.x * cos + self.z * sin,
+            y: self.y,
+            z: -self.x * sin + self.z * cos,
+        };
+    }
+
+    /// Rotates by `degrees` around the x, then y, then z axis, each
+    /// counterclockwise looking down from the positive axis.
+    pub fn rotate_xyz(&self, degrees: Vector) -> Vector {
+        let (x, z) = (degrees.x.to_radians(), degrees.z.to_radians());
+        let v = Vector::from(
+            self.x,
+            self.y * x.cos() - self.z * x.sin(),
+            self.y * x.sin() + self.z * x.cos(),
+        );
+        let v = v.rotate_y(degrees.y.to_radians());
+        return Vector::from(
+            v.x * z.cos() - v.y * z.sin(),
+            v.x * z.sin() + v.y * z.cos(),
+            v.z,
+        );
+    }
+
+    /// Parses a vector from the form `x,y,z`.
+    pub fn parse(s: &str) -> Option<Vector> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        return match coords[..] {
+            [x, y, z] => Some(Vector::from(x, y, z)),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector,
+    pub direction: Vector,
+}
+
+#[derive(Clone, Debug)]
+pub enum ReflectType {
+    Diffuse,
+    Specular,
+    Refract {
+        /// Index of refraction, e.g. 1.5 for glass
+        ior: f64,
+        /// Absorption coefficients per meter traveled inside the medium
+        absorption: Vector,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub color: Vector,
+    pub emmission: Vector,
+    pub reflect_type: ReflectType,
+    /// Multiply `color` with the vertex colors of meshes that have them
+    pub vertex_colors: bool,
+    /// Procedural pattern replacing the uniform `color`
+    pub texture: Option<Texture>,
+}
+
+/// Material used by several objects, so that changing it with `--material`
+/// changes all of them. The objects' `material` holds the resolved copy.
+#[derive(Clone, Debug)]
+pub struct SharedMaterial {
+    pub name: String,
+    pub material: Material,
+    /// Indices into `SceneData.objects`, each with its own overrides
+    pub users: Vec<(usize, MaterialOverride)>,
+}
+
+/// Parameters that replace those of a shared material for one object
+#[derive(Clone, Debug, Default)]
+pub struct MaterialOverride {
+    pub color: Option<Vector>,
+    pub emission: Option<Vector>,
+    pub reflect_type: Option<ReflectType>,
+}
+
+impl MaterialOverride {
+    pub fn apply(&self, material: &Material) -> Material {
+        return Material {
+            color: self.color.unwrap_or(material.color),
+            emmission: self.emission.unwrap_or(material.emmission),
+            reflect_type: self
+                .reflect_type
+                .clone()
+                .unwrap_or_else(|| material.reflect_type.clone()),
+            ..material.clone()
+        };
+    }
+
+    /// Parses `color=<r,g,b>` or `emission=<r,g,b>`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (param, value) = s.split_once('=')?;
+        let value = parse_color(value).filter(|v| v.x.min(v.y).min(v.z) >= 0.0)?;
+        return match param {
+            "color" => Some(MaterialOverride {
+                color: Some(value),
+                ..Default::default()
+            }),
+            "emission" => Some(MaterialOverride {
+                emission: Some(value),
+                ..Default::default()
+            }),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SceneData {
+    pub id: String,
+    pub objects: Vec<SceneObjectData>,
+    pub camera: CameraData,
+    /// Named alternatives to `camera`, selected with `--camera=<name>`
+    pub camera_bookmarks: Vec<(String, CameraData)>,
+    pub animation: Animation,
+    /// Light arriving from rays that leave the scene
+    pub environment: Environment,
+    /// Section plane that cuts away geometry in front of the camera
+    pub clip_plane: Option<ClipPlane>,
+    pub render_settings: RenderSettings,
+    /// Materials shared between objects, see `update_materials`
+    pub materials: Vec<SharedMaterial>,
+    /// Point, spot and area lights besides the emissive objects
+    pub lights: Vec<Light>,
+    /// Caustics for `Integrator::PhotonMapping`, see `with_photon_map`
+    pub caustics: Option<Arc<PhotonMap>>,
+    /// Fog filling the scene. It also absorbs all light from the environment,
+    /// so it is meant for closed scenes.
+    pub medium: Option<Medium>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Environment {
+    Black,
+    /// The same radiance in every direction
+    Color(Vector),
+    /// Blends from `bottom` straight down to `top` straight up
+    Gradient {
+        bottom: Vector,
+        top: Vector,
+    },
+    Sky(Sky),
+    /// Equirectangular HDR image
+    Map(Arc<EnvironmentMap>),
+}
+
+impl Environment {
+    pub fn radiance(&self, direction: Vector) -> Vector {
+        return match self {
+            Environment::Black => Vector::zero(),
+            Environment::Color(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = (0.5 * (direction.y + 1.0)).clamp(0.0, 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Environment::Sky(sky) => sky.radiance(direction),
+            Environment::Map(map) => map.radiance(direction),
+        };
+    }
+
+    /// Parses a constant background `<r,g,b>`, or a gradient
+    /// `<r,g,b>:<r,g,b>` from bottom to top.
+    pub fn parse_background(s: &str) -> Option<Self> {
+        let color = |s: &str| parse_color(s).filter(|c| c.x.min(c.y).min(c.z) >= 0.0);
+        return match s.split_once(':') {
+            Some((bottom, top)) => Some(Environment::Gradient {
+                bottom: color(bottom)?,
+                top: color(top)?,
+            }),
+            None => Some(Environment::Color(color(s)?)),
+        };
+    }
+}
+
+/// Settings that suit a scene, used unless they are given on the command line
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderSettings {
+    pub samples_per_pixel: Option<usize>,
+    pub resolution_y: Option<usize>,
+    /// Bounces after which paths end, defaults to `MAX_DEPTH`
+    pub max_depth: Option<usize>,
+    /// Bounces before Russian roulette may end paths, defaults to
+    /// `MIN_BOUNCES`
+    pub min_bounces: Option<usize>,
+    pub firefly_clamp: Option<f64>,
+}
+
+/// Removes everything on one side of a plane from camera rays, for looking
+/// inside closed scenes and meshes. The cut-away geometry still casts shadows
+/// and reflects light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipPlane {
+    pub point: Vector,
+    /// Points to the side that stays visible
+    pub normal: Vector,
+    /// Show the cut faces of closed objects instead of looking into them
+    pub capped: bool,
+}
+
+impl ClipPlane {
+    /// Parses `<x,y,z>:<nx,ny,nz>`, a point on the plane and its normal.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (point, normal) = s.split_once(':')?;
+        let normal = Vector::parse(normal)?;
+        if normal.dot(&normal) == 0.0 {
+            return None;
+        }
+        return Some(ClipPlane {
+            point: Vector::parse(point)?,
+            normal: normal.normalize(),
+            capped: false,
+        });
+    }
+
+    /// Distances along `ray` between which it is on the visible side
+    fn visible_interval(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let start = (ray.origin - self.point).dot(&self.normal);
+        let speed = ray.direction.dot(&self.normal);
+        let crossing = -start / speed;
+        return if speed > 0.0 {
+            Some((crossing.max(0.0), f64::INFINITY))
+        } else if speed < 0.0 && crossing > 0.0 {
+            Some((0.0, crossing))
+        } else if speed == 0.0 && start >= 0.0 {
+            Some((0.0, f64::INFINITY))
+        } else {
+            None
+        };
+    }
+}
+
+/// Camera to render with instead of the scene's default camera
+#[derive(Clone, Debug, PartialEq)]
+pub enum CameraChoice {
+    Bookmark(String),
+    /// Keep the view direction and move back until the whole scene fits
+    FrameScene,
+    /// Keep the view direction and move until the object fits
+    FrameObject(ObjectRef),
+}
+
+impl CameraChoice {
+    /// Parses `frame`, `frame:<object index or name>` or a bookmark name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name.split_once(':') {
+            None if name == "frame" => Some(CameraChoice::FrameScene),
+            None => Some(CameraChoice::Bookmark(name.to_owned())),
+            Some(("frame", object)) => Some(CameraChoice::FrameObject(parse_object_ref(object)?)),
+            Some(_) => None,
+        };
+    }
+}
+
+/// Pivot of the turntable camera
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrbitCenter {
+    Point(Vector),
+    /// Center of the object's bounding sphere
+    Object(ObjectRef),
+}
+
+impl OrbitCenter {
+    /// Parses `<x,y,z>` or an object index or name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(point) = Vector::parse(name) {
+            return Some(OrbitCenter::Point(point));
+        }
+        return Some(OrbitCenter::Object(parse_object_ref(name)?));
+    }
+}
+
+impl SceneData {
+    /// Sphere enclosing all bounded objects that aren't hidden. Objects that
+    /// enclose the camera or are larger than their distance to it, like the
+    /// large spheres used as walls, are left out unless nothing else is left.
+    pub fn bounding_sphere(&self) -> Option<(Vector, f64)> {
+        let all = self
+            .objects
+            .iter()
+            .filter(|object| object.visibility != Visibility::Hidden)
+            .filter_map(|object| object.bounding_sphere())
+            .collect::<Vec<_>>();
+        // The camera is further from the surface than the radius
+        let content = all
+            .iter()
+            .filter(|(center, radius)| (*center - self.camera.position).magnitude() > 2.0 * radius)
+            .copied()
+            .collect::<Vec<_>>();
+        let spheres = if content.is_empty() { all } else { content };
+        let (first_center, first_radius) = *spheres.first()?;
+        let mut min = first_center - Vector::uniform(first_radius);
+        let mut max = first_center + Vector::uniform(first_radius);
+        for (center, radius) in &spheres {
+            min = Vector::from(
+                min.x.min(center.x - radius),
+                min.y.min(center.y - radius),
+                min.z.min(center.z - radius),
+            );
+            max = Vector::from(
+                max.x.max(center.x + radius),
+                max.y.max(center.y + radius),
+                max.z.max(center.z + radius),
+            );
+        }
+        let center = (min + max) * 0.5;
+        let radius = spheres
+            .iter()
+            .map(|(c, r)| (*c - center).magnitude() + r)
+            .fold(0.0, f64::max);
+        return Some((center, radius));
+    }
+
+    /// Replaces the camera with a bookmark or a framing camera.
+    pub fn select_camera(
+        &mut self,
+        choice: &CameraChoice,
+        render_config: &RenderConfig,
+    ) -> Result<(), String> {
+        let (center, radius) = match choice {
+            CameraChoice::Bookmark(name) => {
+                let (_, camera) = self
+                    .camera_bookmarks
+                    .iter()
+                    .find(|(bookmark, _)| bookmark == name)
+                    .ok_or_else(|| {
+                        let names = self
+                            .camera_bookmarks
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect::<Vec<_>>();
+                        format!(
+                            "Scene {} has no camera {} (bookmarks: {})",
+                            self.id,
+                            name,
+                            if names.is_empty() {
+                                "none".to_owned()
+                            } else {
+                                names.join(", ")
+                            }
+                        )
+                    })?;
+                self.camera = *camera;
+                return Ok(());
+            }
+            CameraChoice::FrameScene => self
+                .bounding_sphere()
+                .ok_or_else(|| format!("Scene {} has no bounded objects", self.id))?,
+            CameraChoice::FrameObject(object) => self.object_bounds(object)?,
+        };
+        self.camera = self.camera.framing(center, radius, render_config);
+        return Ok(());
+    }
+
+    /// Bounding sphere of a single object, for framing and orbiting it
+    fn object_bounds(&self, object: &ObjectRef) -> Result<(Vector, f64), String> {
+        let i = self
+            .find_object(object)
+            .ok_or_else(|| format!("Scene {} has no object {}", self.id, object))?;
+        return self.objects[i]
+            .bounding_sphere()
+            .ok_or_else(|| format!("Object {} is an infinite plane", self.object_label(i)));
+    }
+
+    /// Point the turntable camera orbits around. Without a center in
+    /// `render_config`, that is the object or scene the camera frames, so
+    /// it stays in view, or else the origin.
+    pub fn turntable_center(&self, render_config: &RenderConfig) -> Result<Vector, String> {
+        let (center, _) = match (&render_config.turntable_center, &render_config.camera) {
+            (Some(OrbitCenter::Point(point)), _) => return Ok(*point),
+            (Some(OrbitCenter::Object(object)), _)
+            | (None, Some(CameraChoice::FrameObject(object))) => self.object_bounds(object)?,
+            (None, Some(CameraChoice::FrameScene)) => self
+                .bounding_sphere()
+                .ok_or_else(|| format!("Scene {} has no bounded objects", self.id))?,
+            (None, _) => return Ok(Vector::zero()),
+        };
+        return Ok(center);
+    }
+
+    /// Applies the visibility, camera, clip plane and depth options of
+    /// `render_config` to the scene.
+    pub fn apply_render_config(&mut self, render_config: &RenderConfig) -> Result<(), String> {
+        // Added first, so the other options can refer to them
+        for (primitive, position) in &render_config.added_objects {
+            self.objects.push(SceneObjectData {
+                type_: SceneObject::Mesh(primitive.to_mesh()),
+                position: *position,
+                material: Material {
+                    color: Vector::uniform(0.75),
+                    emmission: Vector::zero(),
+                    reflect_type: ReflectType::Diffuse,
+                    vertex_colors: false,
+                    texture: None,
+                },
+                visibility: Visibility::Visible,
+                name: Some(primitive.to_string()),
+            });
+        }
+        let visibility_overrides = [
+            (
+                &render_config.camera_invisible_objects,
+                Visibility::CameraInvisible,
+            ),
+            (&render_config.hidden_objects, Visibility::Hidden),
+        ];
+        for (objects, visibility) in visibility_overrides {
+            for object in objects {
+                let i = self
+                    .find_object(object)
+                    .ok_or_else(|| format!("Scene {} has no object {}", self.id, object))?;
+                self.objects[i].visibility = visibility;
+            }
+        }
+        if let Some(choice) = &render_config.camera {
+            self.select_camera(choice, render_config)?;
+        }
+        self.clip_plane = render_config.clip_plane;
+        for (name, edit) in &render_config.material_edits {
+            let shared = self
+                .materials
+                .iter_mut()
+                .find(|shared| shared.name == *name)
+                .ok_or_else(|| format!("Scene {} has no material {}", self.id, name))?;
+            shared.material = edit.apply(&shared.material);
+        }
+        self.update_materials();
+        if let Some(background) = &render_config.background {
+            self.environment = background.clone();
+        }
+        if let Some(max_depth) = render_config.max_depth {
+            self.render_settings.max_depth = Some(max_depth);
+        }
+        if let Some(min_bounces) = render_config.min_bounces {
+            self.render_settings.min_bounces = Some(min_bounces);
+        }
+        return Ok(());
+    }
+
+    /// The scene with a caustics photon map when rendering with
+    /// `Integrator::PhotonMapping`, built with the seed of `render_config`.
+    pub fn with_photon_map(&self, render_config: &RenderConfig) -> Cow<'_, SceneData> {
+        let Integrator::PhotonMapping { photons, radius } = render_config.integrator else {
+            return Cow::Borrowed(self);
+        };
+        if let Some(seed) = render_config.seed {
+            seed_rand01(Some(seed ^ 0x5851_F42D_4C95_7F2D));
+        }
+        let caustics = PhotonMap::build(self, photons, radius);
+        if render_config.seed.is_some() {
+            seed_rand01(None);
+        }
+        return Cow::Owned(SceneData {
+            caustics: Some(Arc::new(caustics)),
+            ..self.clone()
+        });
+    }
+
+    /// Sets the material of every object that uses a shared material, after
+    /// the scene was built or a shared material changed.
+    pub fn update_materials(&mut self) {
+        for shared in &self.materials {
+            for (i, overrides) in &shared.users {
+                if let Some(object) = self.objects.get_mut(*i) {
+                    object.material = overrides.apply(&shared.material);
+                }
+            }
+        }
+    }
+
+    pub fn find_object(&self, object: &ObjectRef) -> Option<usize> {
+        return match object {
+            ObjectRef::Index(i) => Some(*i).filter(|i| *i < self.objects.len()),
+            ObjectRef::Name(name) => self
+                .objects
+                .iter()
+                .position(|o| o.name.as_ref() == Some(name)),
+        };
+    }
+
+    /// Index of the object, followed by its name if it has one
+    pub fn object_label(&self, i: usize) -> String {
+        return match &self.objects[i].name {
+            Some(name) => format!("{} \"{}\"", i, name),
+            None => i.to_string(),
+        };
+    }
+
+    /// Number of triangles in all meshes of the scene
+    pub fn triangle_count(&self) -> usize {
+        return self
+            .objects
+            .iter()
+            .map(|object| match &object.type_ {
+                SceneObject::Mesh(mesh) => mesh.triangle_count(),
+                _ => 0,
+            })
+            .sum();
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CameraData {
+    pub position: Vector,
+    /// normal to sensor plane
+    pub direction: Vector,
+    /// in meters
+    pub focal_length: f64,
+}
+
+impl CameraData {
+    /// Camera with the same direction and lens, moved so that a sphere
+    /// around `center` just fits into the image.
+    pub fn framing(&self, center: Vector, radius: f64, render_config: &RenderConfig) -> CameraData {
+        let sensor_height =
+            SENSOR_WIDTH * render_config.resolution_y as f64 / render_config.resolution_x() as f64;
+        let half_fov = (sensor_height.min(SENSOR_WIDTH) / 2.0 / self.focal_length).atan();
+        let direction = self.direction.normalize();
+        // Rays start at the pinhole, one focal length in front of the position
+        let distance = radius / half_fov.sin() + self.focal_length;
+        return CameraData {
+            position: center - direction * distance,
+            direction: self.direction,
+            focal_length: self.focal_length,
+        };
+    }
+
+    /// Camera moved on a circle around the vertical axis through `center`.
+    pub fn orbited(&self, center: Vector, angle: f64) -> CameraData {
+        CameraData {
+            position: center + (self.position - center).rotate_y(angle),
+            direction: self.direction.rotate_y(angle),
+            focal_length: self.focal_length,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SceneObjectData {
+    pub type_: SceneObject,
+    pub position: Vector,
+    pub material: Material,
+    pub visibility: Visibility,
+    /// Shown in logs, and usable instead of the index on the command line
+    pub name: Option<String>,
+}
+
+/// Object given on the command line, by index or by name
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectRef {
+    Index(usize),
+    Name(String),
+}
+
+impl Display for ObjectRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectRef::Index(i) => write!(f, "{}", i),
+            ObjectRef::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+/// Which rays an object is seen by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Visibility {
+    Visible,
+    /// Not seen by camera rays, but still casts shadows and shows up in
+    /// reflections and indirect light
+    CameraInvisible,
+    /// Excluded from the render
+    Hidden,
+}
+
+impl SceneObjectData {
+    /// Center and radius of a sphere enclosing the object, or None for
+    /// infinite planes
+    pub fn bounding_sphere(&self) -> Option<(Vector, f64)> {
+        return match &self.type_ {
+            SceneObject::Sphere { radius } => Some((self.position, *radius)),
+            SceneObject::Plane { .. } => None,
+            SceneObject::Disc { radius, .. } => Some((self.position, *radius)),
+            SceneObject::Mesh(mesh) => Some((
+                self.position + mesh.bounding_sphere.position,
+                mesh.bounding_sphere.radius,
+            )),
+        };
+    }
+
+    /// Number of primitives `intersect` tests against `ray`
+    fn primitive_tests(&self, ray: &Ray) -> u64 {
+        return match &self.type_ {
+            SceneObject::Mesh(mesh) => match intersect_sphere(
+                mesh.bounding_sphere.position + self.position,
+                mesh.bounding_sphere.radius,
+                ray,
+            ) {
+                IntersectResult::NoHit => 1,
+                IntersectResult::Hit(_) => 1 + mesh.triangle_count() as u64,
+            },
+            _ => 1,
+        };
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> IntersectResult {
+        return match &self.type_ {
+            SceneObject::Sphere { radius } => intersect_sphere(self.position, *radius, ray),
+
+            SceneObject::Plane { normal } => intersect_plane(self.position, *normal, ray),
+
+            SceneObject::Disc { normal, radius } => {
+                match intersect_plane(self.position, *normal, ray) {
+                    IntersectResult::Hit(hit)
+                        if (hit.intersection - self.position).magnitude() <= *radius =>
+                    {
+                        IntersectResult::Hit(hit)
+                    }
+                    _ => IntersectResult::NoHit,
+                }
+            }
+
+            SceneObject::Mesh(mesh) => match intersect_sphere(
+                mesh.bounding_sphere.position + self.position,
+                mesh.bounding_sphere.radius,
+                ray,
+            ) {
+                IntersectResult::NoHit => IntersectResult::NoHit,
+                IntersectResult::Hit(_) => {
+                    // Intersect in mesh space instead of moving every triangle
+                    let local_ray = Ray {
+                        origin: ray.origin - self.position,
+                        direction: ray.direction,
+                    };
+                    match mesh.intersect(&local_ray) {
+                        IntersectResult::NoHit => IntersectResult::NoHit,
+                        IntersectResult::Hit(hit) => IntersectResult::Hit(Hit {
+                            intersection: hit.intersection + self.position,
+                            ..hit
+                        }),
+                    }
+                }
+            },
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SceneObject {
+    Sphere {
+        radius: f64,
+    },
+    /// Infinite plane through the object position
+    Plane {
+        normal: Vector,
+    },
+    /// Flat disc centered at the object position
+    Disc {
+        normal: Vector,
+        radius: f64,
+    },
+    Mesh(Mesh),
+}
+
+#[derive(Clone, Debug)]
+struct StandaloneSphere {
+    position: Vector,
+    radius: f64,
+}
+
+/// Smallest accepted hit distance for coordinates of magnitude `scale`.
+/// Rounding errors grow with the magnitude, so a fixed epsilon is either
+/// too small for large scenes or cuts off hits in small ones.
+fn min_hit_distance(scale: f64) -> f64 {
+    return HIT_EPSILON * scale.max(1.0);
+}
+
+fn intersect_sphere(position: Vector, radius: f64, ray: &Ray) -> IntersectResult {
+    let op: Vector = position - ray.origin;
+    let eps = min_hit_distance(ray.origin.max_abs().max(radius));
+    let b = op.dot(&ray.direction);
+    let mut det = b.powi(2) - op.dot(&op) + radius.powi(2);
+    if det < 0.0 {
+        return IntersectResult::NoHit;
+    } else {
+        det = det.sqrt();
+    }
+    let t = if b - det >= eps {
+        b - det
+    } else if b + det >= eps {
+        b + det
+    } else {
+        return IntersectResult::NoHit;
+    };
+
+    let xmin = ray.origin + ray.direction * t;
+    let nmin = (xmin - position).normalize();
+
+    return IntersectResult::Hit(Hit {
+        distance: t,
+        intersection: xmin,
+        normal: nmin,
+        vertex_color: None,
+    });
+}
+
+fn intersect_plane(position: Vector, normal: Vector, ray: &Ray) -> IntersectResult {
+    let eps = min_hit_distance(ray.origin.max_abs().max(position.max_abs()));
+    let normal = normal.normalize();
+    let denominator = normal.dot(&ray.direction);
+    if denominator.abs() < 1e-12 {
+        // Ray is parallel to the plane
+        return IntersectResult::NoHit;
+    }
+    let t = (position - ray.origin).dot(&normal) / denominator;
+    if t < eps {
+        return IntersectResult::NoHit;
+    }
+
+    return IntersectResult::Hit(Hit {
+        distance: t,
+        intersection: ray.origin + ray.direction * t,
+        normal,
+        vertex_color: None,
+    });
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Hit {
+    pub distance: f64,
+    pub intersection: Vector,
+    pub normal: Vector,
+    /// Interpolated vertex color, for meshes that have them
+    pub vertex_color: Option<Vector>,
+}
+
+impl Hit {
+    /// Ray leaving the surface at the intersection in `direction`.
+    pub fn spawn_ray(&self, direction: Vector) -> Ray {
+        return Ray {
+            origin: offset_origin(self.intersection, self.normal, direction),
+            direction,
+        };
+    }
+}
+
+/// Moves `point` off a surface with geometric normal `normal`, to the side
+/// `direction` points to. The computed intersection is only accurate up to
+/// a rounding error that grows with its magnitude, and may lie slightly
+/// behind the surface, so the offset scales with the magnitude as well.
+fn offset_origin(point: Vector, normal: Vector, direction: Vector) -> Vector {
+    let offset = normal * (RAY_OFFSET * point.max_abs().max(1.0));
+    return if normal.dot(&direction) >= 0.0 {
+        point + offset
+    } else {
+        point - offset
+    };
+}
+
+pub enum IntersectResult {
+    NoHit,
+    Hit(Hit),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum SceneIntersectResult {
+    NoHit,
+    Hit { object_id: usize, hit: Hit },
+}
+
+thread_local! {
+    /// Rays traced by this thread since the last call to `take_ray_count`
+    static RAY_COUNT: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// Intersection work done for one pixel, collected by `render_heatmap`, or
+/// for one row by `render_profiled`
+#[derive(Clone, Debug, Default)]
+pub struct RayStats {
+    pub rays: u64,
+    /// Time spent finding the closest hit, without counting the tests
+    pub intersection_time: Duration,
+    /// Tests against spheres, planes, discs, mesh bounding spheres and triangles
+    pub primitive_tests: u64,
+    /// Primitive tests by object index
+    pub object_tests: Vec<u64>,
+    /// Paths by the number of rays they traced. Refraction can split a path,
+    /// so one sample may count several.
+    pub path_lengths: Vec<u64>,
+}
+
+impl RayStats {
+    /// Average number of rays per path, None if no path was traced
+    pub fn average_path_length(&self) -> Option<f64> {
+        let paths: u64 = self.path_lengths.iter().sum();
+        let rays: u64 = (0..)
+            .zip(&self.path_lengths)
+            .map(|(n, count)| n * count)
+            .sum();
+        return (paths > 0).then(|| rays as f64 / paths as f64);
+    }
+}
+
+thread_local! {
+    /// Statistics updated by `intersect_scene` while set
+    static RAY_STATS: RefCell<Option<RayStats>> = const { RefCell::new(None) };
+}
+/// Number of running `render_heatmap` passes
+static COLLECT_RAY_STATS: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+/// One ray of a path recorded by `trace_pixel_paths`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathSegment {
+    /// Bounces before this ray, 0 for the camera ray
+    pub depth: usize,
+    pub origin: Vector,
+    /// Hit point, scattering point, or one unit along rays that escape
+    pub end: Vector,
+    pub event: PathEvent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathEvent {
+    /// Hit the object with this index
+    Object(usize),
+    /// Scattered in the scene's medium
+    Medium,
+    /// Left the scene
+    Environment,
+}
+
+thread_local! {
+    /// Path segments added by `trace_path` while set
+    static PATH_RECORD: RefCell<Option<Vec<PathSegment>>> = const { RefCell::new(None) };
+}
+/// Number of running `trace_pixel_paths` calls
+static RECORD_PATHS: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+fn record_segment(depth: usize, ray: &Ray, distance: f64, event: PathEvent) {
+    if RECORD_PATHS.load(atomic::Ordering::Relaxed) == 0 {
+        return;
+    }
+    PATH_RECORD.with(|record| {
+        if let Some(segments) = record.borrow_mut().as_mut() {
+            segments.push(PathSegment {
+                depth,
+                origin: ray.origin,
+                end: ray.origin + ray.direction * distance,
+                event,
+            });
+        }
+    });
+}
+
+/// Counts a path that ended after the ray at `depth` while ray statistics
+/// are collected
+fn record_path_end(depth: usize) {
+    if COLLECT_RAY_STATS.load(atomic::Ordering::Relaxed) == 0 {
+        return;
+    }
+    RAY_STATS.with(|stats| {
+        if let Some(stats) = stats.borrow_mut().as_mut() {
+            if stats.path_lengths.len() < depth + 2 {
+                stats.path_lengths.resize(depth + 2, 0);
+            }
+            stats.path_lengths[depth + 1] += 1;
+        }
+    });
+}
+
+fn take_ray_count() -> u64 {
+    return RAY_COUNT.with(|count| count.replace(0));
+}
+
+/// First hit of a camera ray, skipping camera-invisible objects and the parts
+/// of the scene removed by its clip plane.
+pub fn intersect_camera_ray(ray: &Ray, scene: &SceneData) -> SceneIntersectResult {
+    let clip = match &scene.clip_plane {
+        Some(clip) => clip,
+        None => return intersect_scene(ray, &scene.objects, true),
+    };
+    let (start, end) = match clip.visible_interval(ray) {
+        Some(interval) => interval,
+        None => return SceneIntersectResult::NoHit,
+    };
+    let clipped = Ray {
+        origin: ray.origin + ray.direction * start,
+        direction: ray.direction,
+    };
+    return match intersect_scene(&clipped, &scene.objects, true) {
+        SceneIntersectResult::Hit { object_id, hit } if start + hit.distance <= end => {
+            if clip.capped && start > 0.0 && hit.normal.dot(&ray.direction) > 0.0 {
+                // The ray starts inside the object, so it sees the cut face
+                SceneIntersectResult::Hit {
+                    object_id,
+                    hit: Hit {
+                        distance: start,
+                        intersection: clipped.origin,
+                        // Outward normal of the solid, facing the camera
+                        normal: clip.normal * -1.0,
+                        vertex_color: None,
+                    },
+                }
+            } else {
+                SceneIntersectResult::Hit {
+                    object_id,
+                    hit: Hit {
+                        distance: start + hit.distance,
+                        ..hit
+                    },
+                }
+            }
+        }
+        _ => SceneIntersectResult::NoHit,
+    };
+}
+
+/// `camera_ray` skips objects that are invisible to the camera.
+pub fn intersect_scene(
+    ray: &Ray,
+    scene_objects: &[SceneObjectData],
+    camera_ray: bool,
+) -> SceneIntersectResult {
+    RAY_COUNT.with(|count| count.set(count.get() + 1));
+    // Checking the global flag first keeps normal renders from touching RAY_STATS
+    let collect_stats = COLLECT_RAY_STATS.load(atomic::Ordering::Relaxed) > 0
+        && RAY_STATS.with(|stats| stats.borrow().is_some());
+    let start = collect_stats.then(Instant::now);
+    let hittable = |object: &SceneObjectData| match object.visibility {
+        Visibility::Visible => true,
+        Visibility::CameraInvisible => !camera_ray,
+        Visibility::Hidden => false,
+    };
+    let mut min_intersect: SceneIntersectResult = SceneIntersectResult::NoHit;
+
+    for i in (0..scene_objects.len()).rev() {
+        let scene_object = &scene_objects[i];
+        if !hittable(scene_object) {
+            continue;
+        }
+        let intersect = scene_object.intersect(ray);
+        match (intersect, &min_intersect) {
+            (IntersectResult::NoHit, _) => (),
+            (IntersectResult::Hit(new_hit), SceneIntersectResult::NoHit) => {
+                min_intersect = SceneIntersectResult::Hit {
+                    object_id: i,
+                    hit: new_hit,
+                };
+            }
+            (IntersectResult::Hit(new_hit), SceneIntersectResult::Hit { hit, .. }) => {
+                if new_hit.distance < hit.distance {
+                    min_intersect = SceneIntersectResult::Hit {
+                        object_id: i,
+                        hit: new_hit,
+                    };
+                }
+            }
+        }
+    }
+
+    if let Some(start) = start {
+        let intersection_time = start.elapsed();
+        RAY_STATS.with(|stats| {
+            let mut stats = stats.borrow_mut();
+            let stats = stats.as_mut().unwrap();
+            stats.rays += 1;
+            stats.intersection_time += intersection_time;
+            for (i, scene_object) in scene_objects.iter().enumerate() {
+                if hittable(scene_object) {
+                    let tests = scene_object.primitive_tests(ray);
+                    stats.primitive_tests += tests;
+                    stats.object_tests[i] += tests;
+                }
+            }
+        });
+    }
+    return min_intersect;
+}
+
+pub const MAX_DEPTH: usize = 12;
+/// Bounces before Russian roulette may end a path
+pub const MIN_BOUNCES: usize = 5;
+pub fn radiance(ray: &Ray, depth: usize, scene: &SceneData) -> Vector {
+    let mut radiance = Vector::zero();
+    let mut pending = vec![PathRay {
+        ray: *ray,
+        depth,
+        skip_emission: SkipEmission::Nothing,
+        throughput: Vector::uniform(1.0),
+    }];
+    while let Some(path) = pending.pop() {
+        radiance = radiance + trace_path(path, scene, &mut pending);
+    }
+    return radiance;
+}
+
+/// Decides whether a path continues to `new_depth`. After the minimum bounces,
+/// paths whose `throughput`, the fraction of light they still carry to the
+/// camera, is low are likely ended. Returns the survival probability to
+/// divide by, so the estimate stays unbiased.
+fn russian_roulette(new_depth: usize, throughput: Vector, scene: &SceneData) -> Option<f64> {
+    let settings = &scene.render_settings;
+    if new_depth >= settings.max_depth.unwrap_or(MAX_DEPTH) {
+        return None;
+    }
+    if new_depth <= settings.min_bounces.unwrap_or(MIN_BOUNCES) {
+        return Some(1.0);
+    }
+    let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+    return (rand01() < survival).then_some(survival);
+}
+
+/// Emission that a path must not add because another estimator covers it
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SkipEmission {
+    Nothing,
+    /// After diffuse bounces, whose direct light from emissive meshes was
+    /// already added by `sample_mesh_lights`
+    Meshes,
+    /// After diffuse and then only specular bounces, whose light is in the
+    /// caustics photon map
+    Objects,
+}
+
+/// Next ray of a path
+struct PathRay {
+    ray: Ray,
+    depth: usize,
+    skip_emission: SkipEmission,
+    /// Fraction of the radiance arriving along `ray` that reaches the camera
+    throughput: Vector,
+}
+
+/// Follows a path bounce by bounce and returns the radiance it carries to the
+/// camera. Glass close to the camera traces both reflection and refraction;
+/// the path continues with the reflection and pushes the refracted ray to
+/// `pending`.
+fn trace_path(path: PathRay, scene: &SceneData, pending: &mut Vec<PathRay>) -> Vector {
+    let PathRay {
+        mut ray,
+        mut depth,
+        mut skip_emission,
+        mut throughput,
+    } = path;
+    let scene_objects = &scene.objects;
+    let mut radiance = Vector::zero();
+    loop {
+        let intersection = if depth == 0 {
+            intersect_camera_ray(&ray, scene)
+        } else {
+            intersect_scene(&ray, scene_objects, false)
+        };
+
+        if let Some(medium) = &scene.medium {
+            let distance = medium.sample_distance(rand01());
+            let scatters = match &intersection {
+                SceneIntersectResult::NoHit => true,
+                SceneIntersectResult::Hit { hit, .. } => distance < hit.distance,
+            };
+            if scatters {
+                // Interaction inside the medium before reaching the next surface
+                record_segment(depth, &ray, distance, PathEvent::Medium);
+                let albedo = medium.sigma_s / medium.sigma_t();
+                let Some(survival) = russian_roulette(depth + 1, throughput * albedo, scene) else {
+                    record_path_end(depth);
+                    return radiance;
+                };
+                throughput = throughput * (albedo / survival);
+                ray = Ray {
+                    origin: ray.origin + ray.direction * distance,
+                    direction: medium.sample_phase(ray.direction, rand01(), rand01()),
+                };
+                depth += 1;
+                skip_emission = SkipEmission::Nothing;
+                continue;
+            }
+        }
+
+        let (object_id, hit) = match intersection {
+            SceneIntersectResult::NoHit => {
+                record_segment(depth, &ray, 1.0, PathEvent::Environment);
+                record_path_end(depth);
+                return radiance + throughput * scene.environment.radiance(ray.direction);
+            }
+            SceneIntersectResult::Hit { object_id, hit } => (object_id, hit),
+        };
+        record_segment(depth, &ray, hit.distance, PathEvent::Object(object_id));
+        let object = &scene_objects[object_id];
+        let emission = match (skip_emission, &object.type_) {
+            (SkipEmission::Objects, _) | (SkipEmission::Meshes, SceneObject::Mesh(_)) => {
+                Vector::zero()
+            }
+            _ => object.material.emmission,
+        };
+        // Specular paths that started on a diffuse surface are caustics
+        let specular_skip = match skip_emission {
+            SkipEmission::Meshes | SkipEmission::Objects if scene.caustics.is_some() => {
+                SkipEmission::Objects
+            }
+            _ => SkipEmission::Nothing,
+        };
+        let albedo = match &object.material.texture {
+            Some(texture) => {
+                texture.albedo(object.material.color, hit.intersection, object.position)
+            }
+            None => object.material.color,
+        };
+        let color: Vector = match hit.vertex_color {
+            Some(vertex_color) if object.material.vertex_colors => albedo * vertex_color,
+            _ => albedo,
+        };
+        // Ray is leaving the medium, so it traveled `hit.distance` inside of it
+        if let ReflectType::Refract { absorption, .. } = object.material.reflect_type {
+            if hit.normal.dot(&ray.direction) > 0.0 {
+                throughput = throughput * beer_lambert(absorption, hit.distance);
+            }
+        }
+        radiance = radiance + throughput * emission;
+        let normal_towards_ray = if hit.normal.dot(&ray.direction) < 0.0 {
+            hit.normal
+        } else {
+            hit.normal * -1.0
+        };
+
+        //--- Russian Roulette Ray termination
+        let new_depth = depth + 1;
+        let Some(survival) = russian_roulette(new_depth, throughput * color, scene) else {
+            record_path_end(depth);
+            return radiance;
+        };
+        throughput = throughput * color * (1.0 / survival);
+
+        let mirrored = ray.direction - hit.normal * 2.0 * hit.normal.dot(&ray.direction);
+        let (next_ray, weight) = match object.material.reflect_type {
+            ReflectType::Diffuse => {
+                // Ideal DIFFUSE reflection
+
+                // cosinus-weighted importance sampling
+                let r1: f64 = 2.0 * PI * rand01();
+                let r2: f64 = rand01();
+                let r2s: f64 = r2.sqrt();
+                let w: Vector = normal_towards_ray;
+                let u = (if w.x.abs() > 0.1 {
+                    Vector::from(0.0, 1.0, 0.0)
+                } else {
+                    Vector::from(1.0, 0.0, 0.0)
+                })
+                .cross(&w)
+                .normalize();
+                let v = w.cross(&u);
+                let d =
+                    (u * r1.cos() * r2s + v * r1.sin() * r2s + w * (1.0 - r2).sqrt()).normalize();
+
+                let mut direct = sample_mesh_lights(hit.intersection, w, scene)
+                    + sample_lights(hit.intersection, w, scene);
+                if let Some(caustics) = &scene.caustics {
+                    direct = direct + caustics.radiance(hit.intersection, w);
+                }
+                radiance = radiance + throughput * direct;
+
+                let (d, weight) = match &scene.environment {
+                    // Sample bright parts of the environment for half of the bounces,
+                    // weighted by the combined density of both strategies
+                    Environment::Map(map) => {
+                        let d = if rand01() < 0.5 {
+                            d
+                        } else {
+                            map.sample(rand01(), rand01()).0
+                        };
+                        let cos = d.dot(&w);
+                        if cos <= 0.0 {
+                            record_path_end(depth);
+                            return radiance;
+                        }
+                        (d, (cos / PI) / (0.5 * cos / PI + 0.5 * map.pdf(d)))
+                    }
+                    _ => (d, 1.0),
+                };
+                skip_emission = SkipEmission::Meshes;
+                (hit.spawn_ray(d), weight)
+            }
+            ReflectType::Specular => {
+                // Ideal SPECULAR reflection
+                skip_emission = specular_skip;
+                (hit.spawn_ray(mirrored), 1.0)
+            }
+            ReflectType::Refract { ior, .. } => {
+                // Ideal dielectric REFRACTION
+                skip_emission = specular_skip;
+                match refract(ray.direction, hit.normal, ior) {
+                    Some((tdir, re)) => {
+                        let tr = 1.0 - re;
+                        let p = 0.25 + 0.5 * re;
+                        if new_depth > 2 {
+                            if rand01() < p {
+                                (hit.spawn_ray(mirrored), re / p)
+                            } else {
+                                (hit.spawn_ray(tdir), tr / (1.0 - p))
+                            }
+                        } else {
+                            pending.push(PathRay {
+                                ray: hit.spawn_ray(tdir),
+                                depth: new_depth,
+                                skip_emission: specular_skip,
+                                throughput: throughput * tr,
+                            });
+                            (hit.spawn_ray(mirrored), re)
+                        }
+                    }
+                    None => (hit.spawn_ray(mirrored), 1.0),
+                }
+            }
+        };
+        ray = next_ray;
+        depth = new_depth;
+        throughput = throughput * weight;
+    }
+}
+
+/// Direct light from emissive meshes arriving at `point`, reflected by a
+/// white diffuse surface with the given normal. Samples one point on one of
+/// the meshes, chosen by emitted power.
+fn sample_mesh_lights(point: Vector, normal: Vector, scene: &SceneData) -> Vector {
+    let luminance = |v: Vector| 0.2126 * v.x + 0.7152 * v.y + 0.0722 * v.z;
+    let power = |object: &SceneObjectData| match &object.type_ {
+        SceneObject::Mesh(mesh) if object.visibility != Visibility::Hidden => {
+            mesh.area() * luminance(object.material.emmission)
+        }
+        _ => 0.0,
+    };
+    let total_power: f64 = scene.objects.iter().map(power).sum();
+    if total_power <= 0.0 {
+        return Vector::zero();
+    }
+
+    // Pick a light proportional to its power
+    let mut target = rand01() * total_power;
+    let mut light_id = 0;
+    for (i, object) in scene.objects.iter().enumerate() {
+        if power(object) > 0.0 {
+            light_id = i;
+            target -= power(object);
+            if target < 0.0 {
+                break;
+            }
+        }
+    }
+    let light = &scene.objects[light_id];
+    let SceneObject::Mesh(mesh) = &light.type_ else {
+        unreachable!()
+    };
+    let (light_point, light_normal) = mesh.sample_point(rand01(), rand01(), rand01());
+    let to_light = light.position + light_point - point;
+    let distance = to_light.magnitude();
+    let direction = to_light / distance;
+
+    let cos_surface = normal.dot(&direction);
+    // Emitters emit from both sides
+    let cos_light = light_normal.dot(&direction).abs();
+    if cos_surface <= 0.0 || cos_light <= 0.0 {
+        return Vector::zero();
+    }
+    let shadow_ray = Ray {
+        origin: offset_origin(point, normal, direction),
+        direction,
+    };
+    match intersect_scene(&shadow_ray, &scene.objects, false) {
+        SceneIntersectResult::Hit { object_id, hit }
+            if object_id == light_id
+                && (hit.distance - distance).abs() < 1e-4 * distance.max(1.0) => {}
+        _ => return Vector::zero(),
+    }
+
+    // Probability density of the sampled point per unit area
+    let pdf = power(light) / total_power / mesh.area();
+    let transmittance = match &scene.medium {
+        Some(medium) => (-medium.sigma_t() * distance).exp(),
+        None => 1.0,
+    };
+    return light.material.emmission
+        * (cos_surface / PI * cos_light / distance.powi(2) / pdf)
+        * transmittance;
+}
+
+/// Direct light from `scene.lights` arriving at `point`, reflected by a white
+/// diffuse surface with the given normal. There are usually few lights, so
+/// each of them gets a shadow ray.
+fn sample_lights(point: Vector, normal: Vector, scene: &SceneData) -> Vector {
+    let mut direct = Vector::zero();
+    for light in &scene.lights {
+        let Some((offset, irradiance)) = light.sample(point, rand01(), rand01()) else {
+            continue;
+        };
+        let distance = offset.magnitude();
+        let direction = offset / distance;
+        let cos_surface = normal.dot(&direction);
+        if cos_surface <= 0.0 {
+            continue;
+        }
+        let shadow_ray = Ray {
+            origin: offset_origin(point, normal, direction),
+            direction,
+        };
+        if let SceneIntersectResult::Hit { hit, .. } =
+            intersect_scene(&shadow_ray, &scene.objects, false)
+        {
+            if hit.distance < distance * (1.0 - 1e-6) {
+                continue;
+            }
+        }
+        let transmittance = match &scene.medium {
+            Some(medium) => (-medium.sigma_t() * distance).exp(),
+            None => 1.0,
+        };
+        direct = direct + irradiance * (cos_surface / PI * transmittance);
+    }
+    return direct;
+}
+
+/// Refracted direction and Fresnel reflectance of a ray entering or leaving a
+/// dielectric with index of refraction `ior` through a surface with outward
+/// `normal`. None on total internal reflection.
+fn refract(direction: Vector, normal: Vector, ior: f64) -> Option<(Vector, f64)> {
+    let into = normal.dot(&direction) < 0.0; // Ray from outside going in?
+    let normal_towards_ray = if into { normal } else { normal * -1.0 };
+    let nc = 1.0; // Index of refraction air
+    let nt = ior; // Index of refraction of the material
+    let nnt: f64 = if into { nc / nt } else { nt / nc };
+    let ddn = direction.dot(&normal_towards_ray);
+    let cos2t = 1.0 - nnt.powi(2) * (1.0 - ddn.powi(2));
+    if cos2t < 0.0 {
+        return None;
+    }
+    let tdir = (direction * nnt
+        - normal * (if into { 1.0 } else { -1.0 } * (ddn * nnt + cos2t.sqrt())))
+    .normalize();
+    let a = nt - nc;
+    let b = nt + nc;
+    let r0 = a * a / (b * b);
+    let c = 1.0 - (if into { -ddn } else { tdir.dot(&normal) });
+    return Some((tdir, r0 + (1.0 - r0) * c.powi(5)));
+}
+
+/// Fraction of light transmitted through `distance` of a medium with the
+/// given absorption coefficients.
+fn beer_lambert(absorption: Vector, distance: f64) -> Vector {
+    return Vector::from(
+        (-absorption.x * distance).exp(),
+        (-absorption.y * distance).exp(),
+        (-absorption.z * distance).exp(),
+    );
+}
+
+/// Scales `v` down so that no component exceeds `max`, preserving its hue.
+fn clamp_radiance(v: Vector, max: f64) -> Vector {
+    let max_component = v.x.max(v.y.max(v.z));
+    if max_component > max {
+        return v * (max / max_component);
+    }
+    return v;
+}
+
+/// Emission of the object directly hit by a camera ray.
+fn primary_emission(ray: &Ray, scene: &SceneData) -> Vector {
+    return match intersect_camera_ray(ray, scene) {
+        SceneIntersectResult::NoHit => Vector::zero(),
+        SceneIntersectResult::Hit { object_id, .. } => scene.objects[object_id].material.emmission,
+    };
+}
+
+pub struct RenderConfig {
+    pub samples_per_pixel: usize,
+    pub resolution_y: usize,
+    /// Image width, defaults to a 3:2 aspect ratio
+    pub width: Option<usize>,
+    pub scene_id: SceneId,
+    /// Maximum per-sample radiance; brighter samples are scaled down to remove fireflies
+    pub firefly_clamp: Option<f64>,
+    /// Don't clamp emission seen directly by camera rays
+    pub clamp_indirect_only: bool,
+    /// Write the scene as a pbrt-v3 file to this path instead of rendering
+    pub export_pbrt: Option<String>,
+    /// Path tracing, or a quick preview of the geometry only
+    pub integrator: Integrator,
+    pub tonemap: Tonemap,
+    /// in stops, applied before tonemapping
+    pub exposure: f64,
+    /// Transfer function of the output image, applied after tonemapping
+    pub encoding: ColorEncoding,
+    /// Pixel reconstruction filter
+    pub filter: Filter,
+    /// Render this many frames with the camera orbiting the scene
+    pub turntable_frames: Option<usize>,
+    /// Camera rotation between turntable frames in degrees, defaults to a full circle
+    pub turntable_step: Option<f64>,
+    /// Point or object on the vertical turntable axis, see
+    /// `SceneData::turntable_center`
+    pub turntable_center: Option<OrbitCenter>,
+    /// Render the scene's keyframe animation instead of a single image
+    pub animate: bool,
+    /// Frames of the animation to render, defaults to all keyframed frames
+    pub frame_range: Option<(usize, usize)>,
+    /// Indices of objects to exclude from the render
+    pub hidden_objects: Vec<ObjectRef>,
+    /// Indices of objects that only affect lighting, not seen by camera rays
+    pub camera_invisible_objects: Vec<ObjectRef>,
+    /// Camera bookmark or framing to render with
+    pub camera: Option<CameraChoice>,
+    /// Section plane applied to the scene
+    pub clip_plane: Option<ClipPlane>,
+    /// Overrides the scene's maximum path length
+    pub max_depth: Option<usize>,
+    /// Overrides the scene's bounces before Russian roulette
+    pub min_bounces: Option<usize>,
+    /// Replaces the scene's environment
+    pub background: Option<Environment>,
+    /// Changes to the scene's shared materials, by name
+    pub material_edits: Vec<(String, MaterialOverride)>,
+    /// Primitives added to the scene at these positions
+    pub added_objects: Vec<(Primitive, Vector)>,
+    /// Don't print render progress
+    pub quiet: bool,
+    /// Render deterministically using this random seed
+    pub seed: Option<u64>,
+    /// Print details about this pixel, counted from the top left, after rendering
+    pub inspect: Option<(usize, usize)>,
+    /// Also write a heatmap of the intersection work per pixel
+    pub heatmap: bool,
+    /// Also write the average path length per pixel and a histogram of them
+    pub path_lengths: bool,
+    /// Measure the time and intersection work of each image row
+    pub profile: bool,
+    /// Leave the background empty and write an alpha channel for compositing
+    pub transparent: bool,
+    /// Addresses of worker processes to render the image on
+    pub workers: Vec<String>,
+    /// Stops the render once set, e.g. from another thread. Unfinished
+    /// pixels average the samples taken so far, which are spread over the
+    /// whole pixel, so they look like pixels rendered with fewer samples.
+    pub cancel: Option<Arc<atomic::AtomicBool>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum SceneId {
+    Int(usize),
+    String(String),
+}
+
+impl Display for SceneId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneId::Int(i) => f.write_str(&i.to_string()),
+            SceneId::String(s) => f.write_str(s),
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn from(args: Vec<String>) -> Option<Self> {
+        return Self::with_scene_settings(args, &RenderSettings::default());
+    }
+
+    /// Parses `args` and picks their scene from `scenes`, with the scene's
+    /// render settings as defaults.
+    pub fn with_scene(args: Vec<String>, scenes: &[SceneData]) -> Option<(Self, SceneData)> {
+        let scene = match RenderConfig::from(args.clone())?.scene_id {
+            SceneId::Int(i) => scenes.get(i),
+            SceneId::String(s) => scenes.iter().find(|scene| scene.id == s),
+        }?;
+        let config = RenderConfig::with_scene_settings(args, &scene.render_settings)?;
+        return Some((config, scene.clone()));
+    }
+
+    /// Like `from`, but takes anything that `args` doesn't set from the
+    /// render settings of the selected scene.
+    pub fn with_scene_settings(args: Vec<String>, settings: &RenderSettings) -> Option<Self> {
+        let (flags, positional): (Vec<&String>, Vec<&String>) =
+            args.iter().skip(1).partition(|arg| arg.starts_with("--"));
+
+        let defaults = RenderConfig::default();
+        let scene_defaults = RenderConfig {
+            samples_per_pixel: settings
+                .samples_per_pixel
+                .unwrap_or(defaults.samples_per_pixel),
+            resolution_y: settings.resolution_y.unwrap_or(defaults.resolution_y),
+            firefly_clamp: settings.firefly_clamp.or(defaults.firefly_clamp),
+            ..defaults
+        };
+        let scene_id = |arg: &String| match arg.parse() {
+            Ok(int) => SceneId::Int(int),
+            Err(_) => SceneId::String(arg.clone()),
+        };
+        let mut config = match positional.len() {
+            3 => RenderConfig {
+                samples_per_pixel: positional[0].parse().ok()?,
+                resolution_y: positional[1].parse().ok()?,
+                scene_id: scene_id(positional[2]),
+                ..scene_defaults
+            },
+            1 => RenderConfig {
+                scene_id: scene_id(positional[0]),
+                ..scene_defaults
+            },
+            0 => scene_defaults,
+            _ => return None,
+        };
+
+        let mut clip_capped = false;
+        for flag in flags {
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (flag.as_str(), None),
+            };
+            match (name, value) {
+                ("--firefly-clamp", Some(value)) => {
+                    config.firefly_clamp = Some(value.parse().ok()?)
+                }
+                ("--clamp-indirect-only", None) => config.clamp_indirect_only = true,
+                ("--max-depth", Some(value)) => config.max_depth = Some(value.parse().ok()?),
+                ("--min-bounces", Some(value)) => config.min_bounces = Some(value.parse().ok()?),
+                ("--export-pbrt", Some(value)) => config.export_pbrt = Some(value.to_owned()),
+                ("--integrator", Some(value)) => config.integrator = Integrator::from_name(value)?,
+                ("--tonemap", Some(value)) => config.tonemap = Tonemap::from_name(value)?,
+                ("--exposure", Some(value)) => config.exposure = value.parse().ok()?,
+                ("--filter", Some(value)) => config.filter = Filter::from_name(value)?,
+                ("--encoding", Some(value)) => config.encoding = ColorEncoding::from_name(value)?,
+                ("--turntable", Some(value)) => {
+                    config.turntable_frames = Some(value.parse().ok().filter(|&n| n > 0)?)
+                }
+                ("--turntable-step", Some(value)) => {
+                    config.turntable_step = Some(value.parse().ok()?)
+                }
+                ("--turntable-center", Some(value)) => {
+                    config.turntable_center = Some(OrbitCenter::from_name(value)?)
+                }
+                ("--animate", None) => config.animate = true,
+                ("--frames", Some(value)) => {
+                    let (first, last) = value.split_once("..")?;
+                    let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                    if first > last {
+                        return None;
+                    }
+                    config.animate = true;
+                    config.frame_range = Some((first, last));
+                }
+                ("--width", Some(value)) => {
+                    let width = value.parse().ok()?;
+                    if width == 0 {
+                        return None;
+                    }
+                    config.width = Some(width);
+                }
+                ("--aspect", Some(value)) => {
+                    let (w, h) = value.split_once(':')?;
+                    let (w, h): (usize, usize) = (w.parse().ok()?, h.parse().ok()?);
+                    if w == 0 || h == 0 {
+                        return None;
+                    }
+                    config.width = Some((config.resolution_y * w).div_ceil(h));
+                }
+                ("--hide", Some(value)) => config.hidden_objects = parse_object_refs(value)?,
+                ("--camera", Some(value)) => config.camera = Some(CameraChoice::from_name(value)?),
+                ("--clip", Some(value)) => config.clip_plane = Some(ClipPlane::parse(value)?),
+                ("--clip-capped", None) => clip_capped = true,
+                ("--material", Some(value)) => {
+                    let (name, edit) = value.split_once(':')?;
+                    let edit = MaterialOverride::parse(edit)?;
+                    config.material_edits.push((name.to_owned(), edit))
+                }
+                ("--add", Some(value)) => {
+                    let (primitive, position) = match value.split_once('@') {
+                        Some((primitive, position)) => (primitive, Vector::parse(position)?),
+                        None => (value, Vector::zero()),
+                    };
+                    config
+                        .added_objects
+                        .push((Primitive::parse(primitive)?, position))
+                }
+                ("--background", Some(value)) => {
+                    config.background = Some(Environment::parse_background(value)?)
+                }
+                ("--camera-invisible", Some(value)) => {
+                    config.camera_invisible_objects = parse_object_refs(value)?
+                }
+                ("--quiet", None) => config.quiet = true,
+                // Sets the log level, which the binary reads before loading scenes
+                ("--verbose", None) => (),
+                ("--seed", Some(value)) => config.seed = Some(value.parse().ok()?),
+                ("--heatmap", None) => config.heatmap = true,
+                ("--path-lengths", None) => config.path_lengths = true,
+                ("--profile", None) => config.profile = true,
+                ("--transparent", None) => config.transparent = true,
+                ("--workers", Some(value)) => {
+                    config.workers = value.split(',').map(str::to_owned).collect()
+                }
+                ("--inspect", Some(value)) => {
+                    let (x, y) = value.split_once(',')?;
+                    config.inspect = Some((x.trim().parse().ok()?, y.trim().parse().ok()?));
+                }
+                _ => return None,
+            }
+        }
+        if config.resolution_y == 0 {
+            return None;
+        }
+        if clip_capped {
+            config.clip_plane.as_mut()?.capped = true;
+        }
+        if config.animate && config.turntable_frames.is_some() {
+            return None;
+        }
+        if (config.inspect.is_some()
+            || config.heatmap
+            || config.path_lengths
+            || config.profile
+            || !config.workers.is_empty())
+            && (config.animate || config.turntable_frames.is_some())
+        {
+            return None;
+        }
+        // Workers render the tiles, so there is nothing to measure locally
+        if config.profile && !config.workers.is_empty() {
+            return None;
+        }
+        if let Some((x, y)) = config.inspect {
+            if x >= config.resolution_x() || y >= config.resolution_y {
+                return None;
+            }
+        }
+        return Some(config);
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: 4000,
+            resolution_y: 600,
+            width: None,
+            scene_id: SceneId::Int(0),
+            firefly_clamp: None,
+            clamp_indirect_only: false,
+            export_pbrt: None,
+            integrator: Integrator::PathTracing,
+            tonemap: Tonemap::Clamp,
+            exposure: 0.0,
+            encoding: ColorEncoding::Srgb,
+            filter: Filter::default(),
+            turntable_frames: None,
+            turntable_step: None,
+            turntable_center: None,
+            animate: false,
+            frame_range: None,
+            hidden_objects: Vec::new(),
+            camera_invisible_objects: Vec::new(),
+            camera: None,
+            clip_plane: None,
+            max_depth: None,
+            min_bounces: None,
+            background: None,
+            material_edits: Vec::new(),
+            added_objects: Vec::new(),
+            quiet: false,
+            seed: None,
+            inspect: None,
+            heatmap: false,
+            path_lengths: false,
+            profile: false,
+            transparent: false,
+            workers: Vec::new(),
+            cancel: None,
+        }
+    }
+}
+
+/// Adds the path of the file that failed to load to an error message
+fn with_path<T>(result: Result<T, std::io::Error>, path: &str) -> Result<T, std::io::Error> {
+    return result.map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path, e)));
+}
+
+/// Parses an object index, or else takes the value as a name
+fn parse_object_ref(value: &str) -> Option<ObjectRef> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    return Some(match value.parse() {
+        Ok(i) => ObjectRef::Index(i),
+        Err(_) => ObjectRef::Name(value.to_owned()),
+    });
+}
+
+/// Parses a comma separated list like "0,3,left wall"
+fn parse_object_refs(value: &str) -> Option<Vec<ObjectRef>> {
+    return value.split(',').map(parse_object_ref).collect();
+}
+
+impl RenderConfig {
+    pub fn resolution_x(&self) -> usize {
+        return self.width.unwrap_or(self.resolution_y * 3 / 2);
+    }
+
+    /// Output color of linear `radiance`: exposed, tonemapped and encoded.
+    /// Every image writer goes through here, so nothing is applied twice.
+    pub fn display(&self, radiance: Vector) -> [u8; 3] {
+        let mapped = self.tonemap.apply(radiance, self.exposure);
+        let channel = |x: f64| (255.0 * self.encoding.encode(x) + 0.5) as u8;
+        return [channel(mapped.x), channel(mapped.y), channel(mapped.z)];
+    }
+
+    pub fn cancelled(&self) -> bool {
+        return self
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(atomic::Ordering::Relaxed));
+    }
+}
+
+/// Samples between checks for cancellation. Small enough to stop within
+/// milliseconds, large enough that the atomic load doesn't show up.
+const CANCEL_CHECK_SAMPLES: usize = 16;
+
+/// Pinhole camera mapping positions on the sensor to rays
+struct Sensor {
+    origin: Vector,
+    lens_center: Vector,
+    /// Orthogonal axes spanning the sensor plane
+    su: Vector,
+    sv: Vector,
+    width: f64,
+    height: f64,
+    resx: f64,
+    resy: f64,
+    /// Distribution of the samples around each pixel center
+    filter: FilterSampler,
+}
+
+impl Sensor {
+    fn new(camera: &CameraData, render_config: &RenderConfig) -> Sensor {
+        let view_direction: Vector = camera.direction.normalize();
+        let su: Vector = view_direction
+            .cross(&if view_direction.y.abs() < 0.9 {
+                Vector::from(0.0, 1.0, 0.0)
+            } else {
+                Vector::from(0.0, 0.0, 1.0)
+            })
+            .normalize();
+        return Sensor {
+            origin: camera.position,
+            lens_center: camera.position + view_direction * camera.focal_length,
+            su,
+            sv: su.cross(&view_direction),
+            width: SENSOR_WIDTH,
+            height: SENSOR_WIDTH * render_config.resolution_y as f64
+                / render_config.resolution_x() as f64,
+            resx: render_config.resolution_x() as f64,
+            resy: render_config.resolution_y as f64,
+            filter: FilterSampler::new(&render_config.filter),
+        };
+    }
+
+    /// Ray through the pinhole from sensor position `x`, `y`, given in pixels.
+    /// The sensor image is upside down, so `x` runs from the right and `y` from
+    /// the top of the final image.
+    fn ray(&self, x: f64, y: f64) -> Ray {
+        let sx = (x / self.resx - 0.5) * self.width;
+        let sy = (y / self.resy - 0.5) * self.height;
+        let sensor_pos = self.origin + self.su * sx + self.sv * sy;
+        return Ray {
+            origin: self.lens_center,
+            direction: (self.lens_center - sensor_pos).normalize(),
+        };
+    }
+}
+
+/// Averages all samples of the pixel at `pixel_index`, counted from the bottom
+/// right of the image. Also returns the fraction of samples that hit an object,
+/// which is always 1 unless the background is transparent.
+fn render_pixel(
+    scene: &SceneData,
+    render_config: &RenderConfig,
+    sensor: &Sensor,
+    pixel_index: usize,
+) -> (Vector, f64) {
+    let resx = render_config.resolution_x();
+    let y = render_config.resolution_y - 1 - pixel_index / resx;
+    let x = pixel_index % resx;
+
+    // Seed each pixel separately so the result doesn't depend on thread scheduling
+    if let Some(seed) = render_config.seed {
+        seed_rand01(Some(
+            seed.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                .wrapping_add(pixel_index as u64),
+        ));
+    }
+
+    let mut radiance_v: Vector = Vector::zero();
+    let mut coverage = 0.0;
+
+    // Stratify the filter samples over its footprint
+    let strata = sampler::n_rooks(render_config.samples_per_pixel, rand01);
+    let mut sample_count = 0;
+    for (u, v) in strata {
+        // Checked within the pixel, so high sample counts stop quickly too
+        if sample_count % CANCEL_CHECK_SAMPLES == 0 && render_config.cancelled() {
+            break;
+        }
+        sample_count += 1;
+        let xfilter = sensor.filter.sample(u);
+        let yfilter = sensor.filter.sample(v);
+
+        let ray = sensor.ray(x as f64 + 0.5 + xfilter, y as f64 + 0.5 + yfilter);
+
+        if render_config.transparent {
+            // The environment still lights the objects, but isn't seen directly
+            if let SceneIntersectResult::NoHit = intersect_camera_ray(&ray, scene) {
+                continue;
+            }
+        }
+        coverage += 1.0;
+
+        // evaluate radiance from this ray and accumulate
+        let mut sample = render_config.integrator.trace(&ray, scene);
+        if let Some(max) = render_config.firefly_clamp {
+            let unclamped = if render_config.clamp_indirect_only {
+                primary_emission(&ray, scene)
+            } else {
+                Vector::zero()
+            };
+            sample = unclamped + clamp_radiance(sample - unclamped, max);
+        }
+        radiance_v = radiance_v + sample;
+    }
+    if render_config.seed.is_some() {
+        seed_rand01(None);
+    }
+    if sample_count == 0 {
+        return (Vector::zero(), 0.0);
+    }
+    // normalize radiance by number of samples
+    radiance_v = radiance_v / sample_count as f64;
+    return (radiance_v, coverage / sample_count as f64);
+}
+
+/// Calls `f` for each pixel index, on all cores with the `parallel` feature.
+/// Mock random numbers are taken in a fixed order, so they stay sequential.
+fn map_pixels<T: Send>(pixels: Range<usize>, f: impl Fn(usize) -> T + Sync + Send) -> Vec<T> {
+    #[cfg(feature = "parallel")]
+    if !MOCK_RANDOM {
+        return pixels.into_par_iter().map(f).collect();
+    }
+    return pixels.map(f).collect();
+}
+
+/// Whether finishing item `finished` of `total` completes another tenth of
+/// them, to log the progress of many small steps without flooding the log
+fn tenths_passed(finished: usize, total: usize) -> bool {
+    return finished * 10 / total != (finished - 1) * 10 / total;
+}
+
+/// Renders `scene`, logging progress as info events. Returns the unclamped
+/// radiance of each pixel, starting at the bottom right of the image.
+pub fn render(scene: &SceneData, render_config: &RenderConfig) -> Vec<Vector> {
+    return render_with_alpha(scene, render_config).0;
+}
+
+/// Like `render`, but also returns the alpha of each pixel. Colors are
+/// premultiplied by alpha, which is 0 where the background is transparent.
+pub fn render_with_alpha(
+    scene: &SceneData,
+    render_config: &RenderConfig,
+) -> (Vec<Vector>, Vec<f64>) {
+    let time_start = Instant::now();
+
+    let scene = &scene.with_photon_map(render_config);
+    if let Some(caustics) = &scene.caustics {
+        info!(photons = caustics.len(), "Stored caustic photons");
+    }
+    let sensor = Sensor::new(&scene.camera, render_config);
+
+    let resy = render_config.resolution_y;
+    let resx: usize = render_config.resolution_x();
+    let grid_size = resx * resy;
+
+    let last_progress_print_time = atomic::AtomicU64::new(0);
+    let max_time_between_progress_prints = 1000;
+    let processed_pixel_count = atomic::AtomicUsize::new(0);
+    let traced_ray_count = atomic::AtomicU64::new(0);
+
+    let print_progress = || {
+        if render_config.quiet {
+            return;
+        }
+        fn fmt(d: std::time::Duration) -> String {
+            let seconds = d.as_secs() % 60;
+            let minutes = (d.as_secs() / 60) % 60;
+            let hours = (d.as_secs() / 60) / 60;
+            if hours == 0 {
+                return format!("{}m:{:0>2}s", minutes, seconds);
+            }
+            format!("{}:{:0>2}:{:0>2}", hours, minutes, seconds)
+        }
+        /// Abbreviates large rates, e.g. 1.5M
+        fn fmt_rate(per_second: f64) -> String {
+            return match per_second {
+                r if r >= 1e9 => format!("{:.1}G", r / 1e9),
+                r if r >= 1e6 => format!("{:.1}M", r / 1e6),
+                r if r >= 1e3 => format!("{:.1}k", r / 1e3),
+                r => format!("{:.0}", r),
+            };
+        }
+        let processed_pixels = processed_pixel_count.load(atomic::Ordering::Relaxed);
+        let processed_percentage = processed_pixels as f64 / (grid_size) as f64;
+        let elapsed = time_start.elapsed();
+        let seconds = elapsed.as_secs_f64().max(1e-3);
+        let total = Duration::from_secs_f64(
+            (seconds / processed_percentage).min(Duration::MAX.as_secs_f64() / 2.0),
+        );
+        let samples_per_second =
+            (processed_pixels * render_config.samples_per_pixel) as f64 / seconds;
+        let rays_per_second = traced_ray_count.load(atomic::Ordering::Relaxed) as f64 / seconds;
+        info!(
+            percent = 100.0 * processed_percentage,
+            elapsed = elapsed.as_secs_f64(),
+            samples_per_second,
+            rays_per_second,
+            "Rendering ... {:3.1}% ({} / {}, {} left, {} samples/s, {} rays/s)",
+            100.0 * processed_percentage,
+            fmt(elapsed),
+            fmt(total),
+            fmt(total.saturating_sub(elapsed)),
+            fmt_rate(samples_per_second),
+            fmt_rate(rays_per_second),
+        );
+    };
+
+    let fun = |pixel_index| {
+        // Only the worker that claims the interval logs, the others go on
+        // rendering instead of waiting for the log output
+        let now = time_start.elapsed().as_millis() as u64;
+        let last = last_progress_print_time.load(atomic::Ordering::Relaxed);
+        if last + max_time_between_progress_prints < now
+            && last_progress_print_time
+                .compare_exchange(
+                    last,
+                    now,
+                    atomic::Ordering::Relaxed,
+                    atomic::Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            print_progress();
+        }
+
+        let pixel = render_pixel(scene, render_config, &sensor, pixel_index);
+        traced_ray_count.fetch_add(take_ray_count(), atomic::Ordering::Relaxed);
+        processed_pixel_count.fetch_add(1, atomic::Ordering::Relaxed);
+
+        pixel
+    };
+    let pixels: (Vec<Vector>, Vec<f64>) = map_pixels(0..grid_size, fun).into_iter().unzip();
+
+    print_progress();
+
+    return pixels;
+}
+
+/// Diagnostic pass that traces the same paths as `render`, but returns the
+/// intersection work of each pixel instead of its color. Starts at the bottom
+/// right of the image.
+pub fn render_heatmap(scene: &SceneData, render_config: &RenderConfig) -> Vec<RayStats> {
+    let scene = &scene.with_photon_map(render_config);
+    let sensor = Sensor::new(&scene.camera, render_config);
+    let grid_size = render_config.resolution_x() * render_config.resolution_y;
+    COLLECT_RAY_STATS.fetch_add(1, atomic::Ordering::Relaxed);
+    let stats = map_pixels(0..grid_size, |pixel_index| {
+        RAY_STATS.with(|stats| {
+            *stats.borrow_mut() = Some(RayStats {
+                object_tests: vec![0; scene.objects.len()],
+                ..RayStats::default()
+            })
+        });
+        render_pixel(scene, render_config, &sensor, pixel_index);
+        take_ray_count();
+        return RAY_STATS.with(|stats| stats.borrow_mut().take().unwrap());
+    });
+    COLLECT_RAY_STATS.fetch_sub(1, atomic::Ordering::Relaxed);
+    return stats;
+}
+
+/// Time and intersection work of one image row, collected by `render_profiled`
+#[derive(Clone, Debug)]
+pub struct TileProfile {
+    /// Counted from the top of the image
+    pub row: usize,
+    /// Index of the thread that rendered the row, 0 without the thread pool
+    pub thread: usize,
+    pub time: Duration,
+    pub stats: RayStats,
+}
+
+/// Like `render_with_alpha`, but renders the image row by row and also
+/// returns the time and intersection work of each row, starting at the
+/// bottom. Measuring adds some overhead to each ray.
+pub fn render_profiled(
+    scene: &SceneData,
+    render_config: &RenderConfig,
+) -> (Vec<Vector>, Vec<f64>, Vec<TileProfile>) {
+    let scene = &scene.with_photon_map(render_config);
+    let sensor = Sensor::new(&scene.camera, render_config);
+    let resx = render_config.resolution_x();
+    let resy = render_config.resolution_y;
+    let finished_rows = atomic::AtomicUsize::new(0);
+
+    COLLECT_RAY_STATS.fetch_add(1, atomic::Ordering::Relaxed);
+    let rows = map_pixels(0..resy, |tile| {
+        let start = Instant::now();
+        RAY_STATS.with(|stats| {
+            *stats.borrow_mut() = Some(RayStats {
+                object_tests: vec![0; scene.objects.len()],
+                ..RayStats::default()
+            })
+        });
+        let pixels: Vec<(Vector, f64)> = (tile * resx..(tile + 1) * resx)
+            .map(|pixel_index| render_pixel(scene, render_config, &sensor, pixel_index))
+            .collect();
+        take_ray_count();
+        let stats = RAY_STATS.with(|stats| stats.borrow_mut().take().unwrap());
+        #[cfg(feature = "parallel")]
+        let thread = rayon::current_thread_index().unwrap_or(0);
+        #[cfg(not(feature = "parallel"))]
+        let thread = 0;
+        let profile = TileProfile {
+            row: resy - 1 - tile,
+            thread,
+            time: start.elapsed(),
+            stats,
+        };
+
+        let finished = finished_rows.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+        if !render_config.quiet && tenths_passed(finished, resy) {
+            info!(
+                rows = finished,
+                total = resy,
+                "Rendering ... {} / {} rows",
+                finished,
+                resy
+            );
+        }
+        return (pixels, profile);
+    });
+    COLLECT_RAY_STATS.fetch_sub(1, atomic::Ordering::Relaxed);
+
+    let mut colors = Vec::with_capacity(resx * resy);
+    let mut alpha = Vec::with_capacity(resx * resy);
+    let mut profiles = Vec::with_capacity(resy);
+    for (pixels, profile) in rows {
+        for (color, pixel_alpha) in pixels {
+            colors.push(color);
+            alpha.push(pixel_alpha);
+        }
+        profiles.push(profile);
+    }
+    return (colors, alpha, profiles);
+}
+
+/// Writes one line per row of `render_profiled` as CSV, with times in
+/// seconds.
+pub fn write_profile(path: &str, profiles: &[TileProfile]) -> std::io::Result<()> {
+    let mut csv = "row,thread,seconds,intersection_seconds,rays,primitive_tests\n".to_owned();
+    for profile in profiles.iter().rev() {
+        csv.push_str(&format!(
+            "{},{},{:.6},{:.6},{},{}\n",
+            profile.row,
+            profile.thread,
+            profile.time.as_secs_f64(),
+            profile.stats.intersection_time.as_secs_f64(),
+            profile.stats.rays,
+            profile.stats.primitive_tests
+        ));
+    }
+    return std::fs::write(path, csv);
+}
+
+/// Writes the primitive tests per pixel as a black-red-yellow-white heatmap,
+/// scaled to the busiest pixel.
+pub fn write_heatmap(
+    path: &str,
+    stats: &[RayStats],
+    render_config: &RenderConfig,
+) -> std::io::Result<()> {
+    let max = stats
+        .iter()
+        .map(|s| s.primitive_tests)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let values: Vec<f64> = stats
+        .iter()
+        .map(|s| s.primitive_tests as f64 / max as f64)
+        .collect();
+    let comment = format!(
+        "primitive tests per pixel, white = {} per sample",
+        max as f64 / render_config.samples_per_pixel as f64
+    );
+    return write_false_color(path, &values, &comment, render_config);
+}
+
+/// Writes the average path length per pixel like `write_heatmap`, with white
+/// at the maximum depth.
+pub fn write_path_length_map(
+    path: &str,
+    stats: &[RayStats],
+    scene: &SceneData,
+    render_config: &RenderConfig,
+) -> std::io::Result<()> {
+    let max_depth = scene.render_settings.max_depth.unwrap_or(MAX_DEPTH);
+    let values: Vec<f64> = stats
+        .iter()
+        .map(|s| s.average_path_length().unwrap_or(0.0) / max_depth as f64)
+        .collect();
+    let comment = format!("average rays per path, white = {}", max_depth);
+    return write_false_color(path, &values, &comment, render_config);
+}
+
+/// Writes `values` between 0 and 1, starting at the bottom right of the
+/// image, as black-red-yellow-white colors.
+fn write_false_color(
+    path: &str,
+    values: &[f64],
+    comment: &str,
+    render_config: &RenderConfig,
+) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(b"P3\n")?;
+    file.write_all(format!("# {}\n", comment).as_bytes())?;
+    file.write_all(
+        format!(
+            "{} {}\n{}\n",
+            render_config.resolution_x(),
+            render_config.resolution_y,
+            255
+        )
+        .as_bytes(),
+    )?;
+    for t in values.iter().rev() {
+        let channel = |offset: f64| (255.0 * (3.0 * t - offset).clamp(0.0, 1.0)).round() as u8;
+        file.write_all(format!("{} {} {} ", channel(0.0), channel(1.0), channel(2.0)).as_bytes())?;
+    }
+    return Ok(());
+}
+
+/// Describes pixel `col`, `row` (counted from the top left) of the rendered
+/// `pixels`: its color and what the camera ray through its center hits first.
+pub fn inspect_pixel(
+    scene: &SceneData,
+    render_config: &RenderConfig,
+    pixels: &[Vector],
+    col: usize,
+    row: usize,
+) -> String {
+    let fmt = |v: Vector| format!("{:.4}, {:.4}, {:.4}", v.x, v.y, v.z);
+    let resx = render_config.resolution_x();
+    let radiance = pixels[pixels.len() - 1 - (row * resx + col)];
+    let [r, g, b] = render_config.display(radiance);
+    let mut out = format!(
+        "Pixel {},{}\n  Radiance: {}\n  Tonemapped: {} {} {}\n  Samples: {}\n",
+        col,
+        row,
+        fmt(radiance),
+        r,
+        g,
+        b,
+        render_config.samples_per_pixel
+    );
+
+    let sensor = Sensor::new(&scene.camera, render_config);
+    let ray = sensor.ray((resx - 1 - col) as f64 + 0.5, row as f64 + 0.5);
+    match intersect_camera_ray(&ray, scene) {
+        SceneIntersectResult::NoHit => out.push_str("  First hit: none (environment)\n"),
+        SceneIntersectResult::Hit { object_id, hit } => {
+            let object = &scene.objects[object_id];
+            let shape = match &object.type_ {
+                SceneObject::Sphere { radius } => format!("sphere, radius {}", radius),
+                SceneObject::Plane { .. } => "plane".to_owned(),
+                SceneObject::Disc { radius, .. } => format!("disc, radius {}", radius),
+                SceneObject::Mesh(mesh) => format!("mesh, {} triangles", mesh.triangle_count()),
+            };
+            out.push_str(&format!(
+                "  First hit: object {} ({}) at distance {:.4}\n    \
+                Point: {}\n    Normal: {}\n    Material: {:?}, color {}, emission {}\n",
+                scene.object_label(object_id),
+                shape,
+                hit.distance,
+                fmt(hit.intersection),
+                fmt(hit.normal),
+                object.material.reflect_type,
+                fmt(object.material.color),
+                fmt(object.material.emmission),
+            ));
+        }
+    }
+
+    out.push_str("  Sample paths, numbered by bounce:\n");
+    for (i, (radiance, segments)) in trace_pixel_paths(scene, render_config, col, row, 3)
+        .into_iter()
+        .enumerate()
+    {
+        out.push_str(&format!("    {}: radiance {}\n", i + 1, fmt(radiance)));
+        for segment in segments {
+            let event = match segment.event {
+                PathEvent::Object(i) => {
+                    format!("object {} at {}", scene.object_label(i), fmt(segment.end))
+                }
+                PathEvent::Medium => format!("medium at {}", fmt(segment.end)),
+                PathEvent::Environment => {
+                    format!("environment towards {}", fmt(segment.end - segment.origin))
+                }
+            };
+            out.push_str(&format!("      {:>2}. {}\n", segment.depth + 1, event));
+        }
+    }
+    return out;
+}
+
+/// Traces `count` paths through the center of the pixel at `col`, `row`,
+/// counted from the top left, and returns the radiance and rays of each.
+/// Refraction can split a path, so segments may branch off at a lower depth.
+pub fn trace_pixel_paths(
+    scene: &SceneData,
+    render_config: &RenderConfig,
+    col: usize,
+    row: usize,
+    count: usize,
+) -> Vec<(Vector, Vec<PathSegment>)> {
+    let sensor = Sensor::new(&scene.camera, render_config);
+    let resx = render_config.resolution_x();
+    let ray = sensor.ray((resx - 1 - col) as f64 + 0.5, row as f64 + 0.5);
+    RECORD_PATHS.fetch_add(1, atomic::Ordering::Relaxed);
+    let paths = (0..count)
+        .map(|_| {
+            PATH_RECORD.with(|record| *record.borrow_mut() = Some(Vec::new()));
+            let radiance = radiance(&ray, 0, scene);
+            let segments = PATH_RECORD.with(|record| record.borrow_mut().take().unwrap());
+            return (radiance, segments);
+        })
+        .collect();
+    RECORD_PATHS.fetch_sub(1, atomic::Ordering::Relaxed);
+    return paths;
+}
+
+pub fn write_ppm(
+    path: &str,
+    pixels: &[Vector],
+    render_config: &RenderConfig,
+    rendering_time: Duration,
+) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    encode_ppm(&mut file, pixels, render_config, rendering_time)?;
+    return file.flush();
+}
+
+/// Writes the image in the format of `write_ppm` to `file`.
+pub fn encode_ppm(
+    file: &mut impl Write,
+    pixels: &[Vector],
+    render_config: &RenderConfig,
+    rendering_time: Duration,
+) -> std::io::Result<()> {
+    file.write_all(b"P3\n")?;
+    file.write_all(
+        format!(
+            "# samplesPerPixel: {}, resolution_y: {}, scene_id: {}\n",
+            render_config.samples_per_pixel, render_config.resolution_y, render_config.scene_id
+        )
+        .as_bytes(),
+    )?;
+    file.write_all(format!("# rendering time: {} s\n", rendering_time.as_secs()).as_bytes())?;
+    file.write_all(
+        format!(
+            "# tonemap: {:?}, exposure: {}, encoding: {:?}\n",
+            render_config.tonemap, render_config.exposure, render_config.encoding
+        )
+        .as_bytes(),
+    )?;
+    file.write_all(
+        format!(
+            "{} {}\n{}\n",
+            render_config.resolution_x(),
+            render_config.resolution_y,
+            255
+        )
+        .as_bytes(),
+    )?;
+    for pixel in pixels.iter().rev() {
+        let [r, g, b] = render_config.display(*pixel);
+        file.write_all(format!("{} {} {} ", r, g, b).as_bytes())?;
+    }
+    return Ok(());
+}
+
+/// Writes a binary PAM image with an alpha channel, as returned by
+/// `render_with_alpha`. Colors are stored straight, not premultiplied.
+pub fn write_pam(
+    path: &str,
+    pixels: &[Vector],
+    alpha: &[f64],
+    render_config: &RenderConfig,
+    rendering_time: Duration,
+) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    encode_pam(&mut file, pixels, alpha, render_config, rendering_time)?;
+    return file.flush();
+}
+
+/// Writes the image in the format of `write_pam` to `file`.
+pub fn encode_pam(
+    file: &mut impl Write,
+    pixels: &[Vector],
+    alpha: &[f64],
+    render_config: &RenderConfig,
+    rendering_time: Duration,
+) -> std::io::Result<()> {
+    file.write_all(b"P7\n")?;
+    file.write_all(
+        format!(
+            "# samplesPerPixel: {}, resolution_y: {}, scene_id: {}\n",
+            render_config.samples_per_pixel, render_config.resolution_y, render_config.scene_id
+        )
+        .as_bytes(),
+    )?;
+    file.write_all(format!("# rendering time: {} s\n", rendering_time.as_secs()).as_bytes())?;
+    file.write_all(
+        format!(
+            "# tonemap: {:?}, exposure: {}, encoding: {:?}\n",
+            render_config.tonemap, render_config.exposure, render_config.encoding
+        )
+        .as_bytes(),
+    )?;
+    file.write_all(
+        format!(
+            "WIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            render_config.resolution_x(),
+            render_config.resolution_y
+        )
+        .as_bytes(),
+    )?;
+    for (pixel, alpha) in pixels.iter().zip(alpha).rev() {
+        let [r, g, b] = if *alpha > 0.0 {
+            render_config.display(*pixel / *alpha)
+        } else {
+            [0; 3]
+        };
+        file.write_all(&[r, g, b, (255.0 * alpha.clamp(0.0, 1.0) + 0.5) as u8])?;
+    }
+    return Ok(());
+}
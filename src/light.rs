@@ -0,0 +1,72 @@
+use crate::Vector;
+
+/// Light source without geometry. Rays never hit it, so it only lights
+/// diffuse surfaces through next event estimation and is not seen by the
+/// camera, in mirrors or through glass.
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub position: Vector,
+    /// Intensity of point and spot lights, radiance of area lights
+    pub emission: Vector,
+    pub shape: LightShape,
+    /// Shown in logs
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightShape {
+    /// Shines equally in all directions
+    Point,
+    /// Point light limited to a cone
+    Spot {
+        direction: Vector,
+        /// Angle between the direction and the edge of the cone in degrees
+        cone_angle: f64,
+        /// Angle in degrees over which the light fades out towards the edge
+        falloff: f64,
+    },
+    /// Rectangle centered at the position with edges `u` and `v`, emitting
+    /// towards `u` cross `v`
+    Area { u: Vector, v: Vector },
+}
+
+impl Light {
+    /// Samples a point on the light as seen from `point`. Returns the offset
+    /// from `point` to the sampled point, and the irradiance that arrives on a
+    /// surface facing it divided by the probability density of the sample.
+    /// None if the light doesn't reach `point`.
+    pub fn sample(&self, point: Vector, r1: f64, r2: f64) -> Option<(Vector, Vector)> {
+        let (offset, factor) = match self.shape {
+            LightShape::Point => (self.position - point, 1.0),
+            LightShape::Spot {
+                direction,
+                cone_angle,
+                falloff,
+            } => {
+                let offset = self.position - point;
+                let cos = -offset.normalize().dot(&direction.normalize());
+                let cos_edge = cone_angle.to_radians().cos();
+                let cos_full = (cone_angle - falloff).max(0.0).to_radians().cos();
+                let factor = if cos >= cos_full {
+                    1.0
+                } else {
+                    let t = ((cos - cos_edge) / (cos_full - cos_edge)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                (offset, factor)
+            }
+            LightShape::Area { u, v } => {
+                let offset = self.position + u * (r1 - 0.5) + v * (r2 - 0.5) - point;
+                let normal = u.cross(&v);
+                let area = normal.magnitude();
+                let cos = -offset.normalize().dot(&(normal / area));
+                (offset, cos.max(0.0) * area)
+            }
+        };
+        let distance_squared = offset.dot(&offset);
+        if factor <= 0.0 || distance_squared == 0.0 {
+            return None;
+        }
+        return Some((offset, self.emission * (factor / distance_squared)));
+    }
+}
@@ -1,112 +1,72 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    io::{Error, ErrorKind, Read},
+    str::FromStr,
 };
 
-use crate::{Mesh, StandaloneSphere, Triangle, Vector};
+use crate::{
+    mesh::{Mesh, Triangle},
+    Vector,
+};
 
-pub(crate) fn load_off(path: &str, scale: f64) -> Result<Mesh, std::io::Error> {
-    let file = File::open(path).unwrap();
-    let mut reader = BufReader::new(file);
+pub fn load_off(path: &str, scale: f64) -> Result<Mesh, Error> {
+    return parse_off_bytes(&std::fs::read(path)?, scale);
+}
 
-    let mut get_line = || -> Result<String, std::io::Error> {
-        let mut line = String::new();
-        while line.len() <= 0 || line.starts_with("#") {
-            line.clear();
-            reader.read_line(&mut line)?;
-            line = line.trim().to_owned();
-        }
-        Ok(line)
+/// Reads an OFF mesh from `reader`, e.g. a file embedded in the binary.
+pub fn parse_off(mut reader: impl Read, scale: f64) -> Result<Mesh, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    return parse_off_bytes(&data, scale);
+}
+
+/// Like `parse_off`, but with the whole file in `data`. Lines and numbers are
+/// parsed in place, without copying them.
+pub fn parse_off_bytes(data: &[u8], scale: f64) -> Result<Mesh, Error> {
+    let mut lines = data
+        .split(|&b| b == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"));
+    let mut get_line = || {
+        return lines
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "File ended early"));
     };
 
-    let bad_data =
-        |reason: &str| Result::Err(std::io::Error::new(std::io::ErrorKind::InvalidData, reason));
+    let bad_data = |reason: &str| Result::Err(Error::new(ErrorKind::InvalidData, reason));
 
     // Read header
-    if get_line()? != "OFF" {
+    if get_line()? != b"OFF" {
         return bad_data("Invalid header");
     }
 
     // Read triangles
-    let counts = get_line()?
-        .split_whitespace()
-        .map(|s| s.parse::<usize>().ok())
-        .collect::<Vec<_>>();
-    if counts.len() != 3 {
+    let Some([vertex_count, face_count, _]) = parse_numbers::<usize, 3>(words(get_line()?)) else {
         return bad_data("Invalid element counts");
-    }
-    let (vertex_count, face_count, _) =
-        (counts[0].unwrap(), counts[1].unwrap(), counts[2].unwrap());
+    };
 
-    let mut vertices = Vec::with_capacity(vertex_count);
-    let mut min_vert = Vector::uniform(f64::INFINITY);
-    let mut max_vert = Vector::uniform(f64::NEG_INFINITY);
+    // The counts come from the file, so don't reserve more than it can hold.
+    // Vertices take at least 5 bytes like "0 0 0", faces at least 7.
+    let mut vertices = Vec::with_capacity(vertex_count.min(data.len() / 5));
     for _ in 0..vertex_count {
-        let line = get_line()?;
-        let coords = line
-            .split_whitespace()
-            .map(|s| s.parse::<f64>().ok())
-            .collect::<Vec<_>>();
-        if coords.len() != 3 {
+        let Some([x, y, z]) = parse_numbers::<f64, 3>(words(get_line()?)) else {
             return bad_data("Invalid vertex coordinates");
-        }
-        let vert = Vector::from(coords[0].unwrap(), coords[1].unwrap(), coords[2].unwrap()) * scale; 
-        vertices.push(vert);
-
-        if vert.x < min_vert.x {
-            min_vert.x = vert.x;
-        }
-        if vert.y < min_vert.y {
-            min_vert.y = vert.y;
-        }
-        if vert.z < min_vert.z {
-            min_vert.z = vert.z;
-        }
-
-        if vert.x > max_vert.x {
-            max_vert.x = vert.x;
-        }
-        if vert.y > max_vert.y {
-            max_vert.y = vert.y;
-        }
-        if vert.z > max_vert.z {
-            max_vert.z = vert.z;
-        }
+        };
+        vertices.push(Vector::from(x, y, z) * scale);
     }
 
-    let bounding_sphere_pos = Vector {
-        x: min_vert.x + max_vert.x * 0.5,
-        y: min_vert.y + max_vert.y * 0.5,
-        z: min_vert.z + max_vert.z * 0.5,
-    };
-    let bounding_sphere = StandaloneSphere {
-        position: bounding_sphere_pos,
-        radius: *vec![(min_vert - bounding_sphere_pos).magnitude(), (max_vert - bounding_sphere_pos).magnitude()]
-            .iter()
-            .max_by(|p1, p2| p1.partial_cmp(&p2).unwrap())
-            .unwrap(),
-    };
-
-    let mut triangles: Vec<Triangle> = Vec::with_capacity(face_count);
+    let mut triangles: Vec<Triangle> = Vec::with_capacity(face_count.min(data.len() / 7));
     for _ in 0..face_count {
         let line = get_line()?;
-        let indices = line
-            .split_whitespace()
-            .map(|s| s.parse::<usize>().ok())
-            .collect::<Vec<_>>();
-        if indices.len() < 4 {
-            return bad_data(format!("Invalid face: {}", line).as_str());
-        }
-        let (count, a, b, c) = (
-            indices[0].unwrap(),
-            indices[1].unwrap(),
-            indices[2].unwrap(),
-            indices[3].unwrap(),
-        );
-        // Optional: read color
-        if count != 3 {
-            // Only triangles are supported
-            return bad_data(format!("Invalid face: {}", line).as_str());
+        // Only triangles are supported. An optional color follows the indices.
+        let indices = parse_numbers::<usize, 4>(words(line).take(4));
+        let Some([3, a, b, c]) = indices else {
+            return bad_data(&format!("Invalid face: {}", String::from_utf8_lossy(line)));
+        };
+        if a.max(b).max(c) >= vertices.len() {
+            return bad_data(&format!(
+                "Invalid vertex index in face: {}",
+                String::from_utf8_lossy(line)
+            ));
         }
         triangles.push(Triangle {
             a: vertices[a],
@@ -115,8 +75,22 @@ pub(crate) fn load_off(path: &str, scale: f64) -> Result<Mesh, std::io::Error> {
         });
     }
 
-    return Ok(Mesh {
-        triangles,
-        bounding_sphere,
-    });
+    return Ok(Mesh::from_triangles(&triangles));
+}
+
+fn words(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    return line
+        .split(u8::is_ascii_whitespace)
+        .filter(|word| !word.is_empty());
+}
+
+/// Exactly `N` numbers from `words`
+fn parse_numbers<'a, T: FromStr + Default + Copy, const N: usize>(
+    mut words: impl Iterator<Item = &'a [u8]>,
+) -> Option<[T; N]> {
+    let mut numbers = [T::default(); N];
+    for number in &mut numbers {
+        *number = std::str::from_utf8(words.next()?).ok()?.parse().ok()?;
+    }
+    return words.next().is_none().then_some(numbers);
 }
@@ -0,0 +1,237 @@
+use std::io::{Error, ErrorKind};
+
+use crate::{
+    color::srgb_to_linear,
+    mesh::{Mesh, Triangle, UpAxis},
+    Vector,
+};
+
+/// Loads an ASCII or binary little-endian PLY file with vertex positions,
+/// optional vertex colors and polygon faces. Polygons are split into
+/// triangle fans.
+pub fn load_ply(path: &str, scale: f64, up: UpAxis) -> Result<Mesh, Error> {
+    return parse_ply(&std::fs::read(path)?, scale, up);
+}
+
+/// Like `load_ply`, with the contents of the file in `data`.
+pub fn parse_ply(data: &[u8], scale: f64, up: UpAxis) -> Result<Mesh, Error> {
+    let header_end = find(data, b"end_header").ok_or_else(|| bad_data("Missing end_header"))?;
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let body_start = header_end
+        + data[header_end..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| bad_data("Missing end_header"))?
+        + 1;
+
+    let (format, elements) = parse_header(&header)?;
+    let mut values = match format.as_str() {
+        "ascii" => Values::Ascii(
+            std::str::from_utf8(&data[body_start..])
+                .map_err(|_| bad_data("Invalid ASCII data"))?
+                .split_whitespace(),
+        ),
+        "binary_little_endian" => Values::Binary(&data[body_start..]),
+        _ => return Err(bad_data(&format!("Unsupported format: {}", format))),
+    };
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut triangles = Vec::new();
+    let mut triangle_colors = Vec::new();
+    for element in &elements {
+        let index = |name: &str| element.properties.iter().position(|p| p.name == name);
+        for _ in 0..element.count {
+            let mut row = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                row.push(match &property.list_count {
+                    None => vec![values.read(&property.type_)?],
+                    Some(count_type) => {
+                        let count = values.read(count_type)? as usize;
+                        (0..count)
+                            .map(|_| values.read(&property.type_))
+                            .collect::<Result<Vec<_>, _>>()?
+                    }
+                });
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = ["x", "y", "z"].map(|axis| index(axis).map(|i| row[i][0]));
+                    let (Some(x), Some(y), Some(z)) = (x, y, z) else {
+                        return Err(bad_data("Vertex without position"));
+                    };
+                    positions.push(up.to_y_up(Vector::from(x, y, z)) * scale);
+
+                    if let (Some(r), Some(g), Some(b)) =
+                        (index("red"), index("green"), index("blue"))
+                    {
+                        let channel = |i: usize| {
+                            let value = row[i][0];
+                            // Integer colors are 8 bit sRGB, floats are taken as linear
+                            return if element.properties[i].type_.starts_with("float")
+                                || element.properties[i].type_ == "double"
+                            {
+                                value
+                            } else {
+                                srgb_to_linear(value / 255.0)
+                            };
+                        };
+                        colors.push(Vector::from(channel(r), channel(g), channel(b)));
+                    }
+                }
+                "face" => {
+                    let indices = index("vertex_indices")
+                        .or_else(|| index("vertex_index"))
+                        .map(|i| &row[i])
+                        .ok_or_else(|| bad_data("Face without vertex indices"))?;
+                    let vertex = |i: f64| {
+                        positions
+                            .get(i as usize)
+                            .copied()
+                            .ok_or_else(|| bad_data(&format!("Invalid vertex index {}", i)))
+                    };
+                    for k in 1..indices.len().saturating_sub(1) {
+                        triangles.push(Triangle {
+                            a: vertex(indices[0])?,
+                            b: vertex(indices[k])?,
+                            c: vertex(indices[k + 1])?,
+                        });
+                        if colors.len() == positions.len() {
+                            let color = |i: f64| colors[i as usize];
+                            triangle_colors.push([
+                                color(indices[0]),
+                                color(indices[k]),
+                                color(indices[k + 1]),
+                            ]);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(bad_data("No faces found"));
+    }
+    let mesh = Mesh::from_triangles(&triangles);
+    return Ok(if triangle_colors.is_empty() {
+        mesh
+    } else {
+        mesh.with_vertex_colors(triangle_colors)
+    });
+}
+
+fn bad_data(reason: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, reason);
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    return data
+        .windows(pattern.len())
+        .position(|window| window == pattern);
+}
+
+struct Property {
+    name: String,
+    type_: String,
+    /// Type of the element count, for list properties
+    list_count: Option<String>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Returns the data format and the declared elements.
+fn parse_header(header: &str) -> Result<(String, Vec<Element>), Error> {
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(bad_data("Invalid header"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", name, _version] => format = Some(name.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| bad_data(&format!("Invalid element count: {}", line)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, type_, name] => elements
+                .last_mut()
+                .ok_or_else(|| bad_data("Property outside of an element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    type_: type_.to_string(),
+                    list_count: Some(count_type.to_string()),
+                }),
+            ["property", type_, name] => elements
+                .last_mut()
+                .ok_or_else(|| bad_data("Property outside of an element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    type_: type_.to_string(),
+                    list_count: None,
+                }),
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| bad_data("Missing format"))?;
+    return Ok((format, elements));
+}
+
+/// Body of the file, read one property value at a time
+enum Values<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary(&'a [u8]),
+}
+
+impl Values<'_> {
+    fn read(&mut self, type_: &str) -> Result<f64, Error> {
+        let truncated = || bad_data("Unexpected end of data");
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(truncated)?;
+                return token
+                    .parse()
+                    .map_err(|_| bad_data(&format!("Invalid value: {}", token)));
+            }
+            Values::Binary(data) => {
+                let size = match type_ {
+                    "char" | "int8" | "uchar" | "uint8" => 1,
+                    "short" | "int16" | "ushort" | "uint16" => 2,
+                    "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => return Err(bad_data(&format!("Unknown property type: {}", type_))),
+                };
+                if data.len() < size {
+                    return Err(truncated());
+                }
+                let (bytes, rest) = data.split_at(size);
+                *data = rest;
+                return Ok(match type_ {
+                    "char" | "int8" => bytes[0] as i8 as f64,
+                    "uchar" | "uint8" => bytes[0] as f64,
+                    "short" | "int16" => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    "ushort" | "uint16" => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    "int" | "int32" => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    "uint" | "uint32" => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    "float" | "float32" => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{Error, ErrorKind};
+
+use crate::{
+    mesh::{Mesh, Triangle, UpAxis},
+    Vector,
+};
+
+/// Loads an ASCII or binary STL file. STL has no units, so `scale` converts
+/// to meters, e.g. 0.001 for the usual millimeters of 3D-print models.
+pub fn load_stl(path: &str, scale: f64, up: UpAxis) -> Result<Mesh, Error> {
+    return parse_stl(&std::fs::read(path)?, scale, up);
+}
+
+/// Like `load_stl`, with the contents of the file in `data`.
+pub fn parse_stl(data: &[u8], scale: f64, up: UpAxis) -> Result<Mesh, Error> {
+    let triangles = if is_binary(data) {
+        parse_binary(data)?
+    } else {
+        parse_ascii(&String::from_utf8_lossy(data))?
+    };
+    let triangles = triangles
+        .into_iter()
+        .map(|tri| Triangle {
+            a: up.to_y_up(tri.a) * scale,
+            b: up.to_y_up(tri.b) * scale,
+            c: up.to_y_up(tri.c) * scale,
+        })
+        .collect::<Vec<_>>();
+    return Ok(Mesh::from_triangles(&triangles));
+}
+
+fn bad_data(reason: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, reason);
+}
+
+/// Binary files may also start with "solid", so go by the size the header's
+/// triangle count implies.
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() < 84 {
+        return false;
+    }
+    let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+    return data.len() == 84 + count * 50 || !data.starts_with(b"solid");
+}
+
+fn parse_binary(data: &[u8]) -> Result<Vec<Triangle>, Error> {
+    if data.len() < 84 {
+        return Err(bad_data("Truncated header"));
+    }
+    let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+    if data.len() < 84 + count * 50 {
+        return Err(bad_data("Truncated triangle data"));
+    }
+
+    let float =
+        |offset: usize| f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as f64;
+    let vertex = |offset: usize| Vector::from(float(offset), float(offset + 4), float(offset + 8));
+    // Each record is a normal, three vertices and a 2 byte attribute count
+    return Ok((0..count)
+        .map(|i| 84 + i * 50)
+        .map(|record| Triangle {
+            a: vertex(record + 12),
+            b: vertex(record + 24),
+            c: vertex(record + 36),
+        })
+        .collect());
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<Triangle>, Error> {
+    let mut triangles = Vec::new();
+    let mut vertices = Vec::with_capacity(3);
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vertex") => {
+                let coords = tokens
+                    .map(|s| s.parse::<f64>().ok())
+                    .collect::<Option<Vec<_>>>();
+                match coords.as_deref() {
+                    Some([x, y, z]) => vertices.push(Vector::from(*x, *y, *z)),
+                    _ => return Err(bad_data(&format!("Invalid vertex: {}", line.trim()))),
+                }
+            }
+            Some("endloop") => {
+                if vertices.len() != 3 {
+                    // Only triangles are supported
+                    return Err(bad_data("Facet is not a triangle"));
+                }
+                triangles.push(Triangle {
+                    a: vertices[0],
+                    b: vertices[1],
+                    c: vertices[2],
+                });
+                vertices.clear();
+            }
+            _ => {}
+        }
+    }
+    if triangles.is_empty() {
+        return Err(bad_data("No facets found"));
+    }
+    return Ok(triangles);
+}
@@ -1,784 +1,482 @@
-mod load_off;
-mod scenes;
-
-#[cfg(test)]
-mod test;
-
-use std::{
-    fmt::Display,
-    io::Write,
-    ops::{Add, Div, Mul, Sub},
-    process::exit,
-    sync::atomic,
-    time::Duration,
-};
-
-use rayon::prelude::*;
-use scenes::load_scenes;
-
-const USE_CULLING: bool = false;
-const PI: f64 = 3.141592653589793;
-
-/// If true, render with a fixed sequence of random numbers.
-const MOCK_RANDOM: bool = false;
-const MOCK_RANDOMS: [f64; 9] = [
-    0.75902418061906407,
-    0.023879213030728041,
-    0.21016190197770457,
-    0.78814922184253244,
-    0.56819568237964491,
-    0.7689823904006352,
-    0.16910304067812287,
-    0.54519597695203492,
-    0.63614169009490062,
-];
-const MOCK_RANDOMS_LEN: usize = MOCK_RANDOMS.len();
-static MOCK_RANDOMS_INDEX: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
-
-// uniform double random generator function
-fn rand01() -> f64 {
-    if MOCK_RANDOM {
-        let i = MOCK_RANDOMS_INDEX.fetch_add(1, atomic::Ordering::Relaxed) % MOCK_RANDOMS_LEN;
-        return MOCK_RANDOMS[i];
-    } else {
-        return rand::random::<f64>();
-    }
-}
-
-fn to_int_with_gamma_correction(x: f64) -> usize {
-    return (255.0 * x.clamp(0.0, 1.0).powf(1.0 / 2.2) + 0.5) as usize;
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Vector {
-    x: f64,
-    y: f64,
-    z: f64,
-}
-
-impl Add<Self> for Vector {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self::Output {
-        return Vector {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
-        };
-    }
-}
-
-impl Sub<Self> for Vector {
-    type Output = Self;
-
-    fn sub(self, other: Self) -> Self::Output {
-        return Vector {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
-        };
-    }
-}
-
-impl Mul<f64> for Vector {
-    type Output = Self;
+use std::{net::TcpListener, ops::RangeInclusive, process::exit, time::Duration};
 
-    fn mul(self, v: f64) -> Self::Output {
-        return Vector {
-            x: self.x * v,
-            y: self.y * v,
-            z: self.z * v,
-        };
-    }
-}
+use tracing::{error, info, level_filters::LevelFilter, warn};
 
-impl Div<f64> for Vector {
-    type Output = Self;
+use path_tracer::{
+    export_pbrt, inspect_pixel, network, render_heatmap, render_profiled, render_with_alpha,
+    scenes::{load_scenes, load_scenes_each},
+    validate, write_heatmap, write_pam, write_path_length_map, write_ppm, write_profile, RayStats,
+    RenderConfig, SceneData, TileProfile, Vector, MAX_DEPTH, MOCK_RANDOM,
+};
 
-    fn div(self, v: f64) -> Self::Output {
-        return Vector {
-            x: self.x / v,
-            y: self.y / v,
-            z: self.z / v,
-        };
-    }
-}
+/// Windows doesn't allow colons in file names
+#[cfg(windows)]
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+#[cfg(not(windows))]
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H:%M:%S";
 
-impl Mul<Self> for Vector {
-    type Output = Self;
+fn main() {
+    let time_start = std::time::Instant::now();
 
-    fn mul(self, other: Self) -> Self::Output {
-        return Vector {
-            x: self.x * other.x,
-            y: self.y * other.y,
-            z: self.z * other.z,
+    // Logs go to stderr, so the output of validate and --inspect stays parsable
+    let args: Vec<String> = std::env::args().collect();
+    let level = if args.iter().any(|arg| arg == "--verbose") {
+        LevelFilter::DEBUG
+    } else if args.iter().any(|arg| arg == "--quiet") {
+        LevelFilter::WARN
+    } else {
+        LevelFilter::INFO
+    };
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .init();
+
+    // Before loading the scenes, so missing files become problems of the scenes using them
+    if args.get(1).map(String::as_str) == Some("validate") {
+        let scenes = load_scenes_each();
+        let selected = if args.len() > 2 {
+            args[2..]
+                .iter()
+                .map(|name| {
+                    scenes
+                        .iter()
+                        .enumerate()
+                        .find(|(i, (id, _))| id == name || i.to_string() == *name)
+                        .map(|(_, scene)| scene)
+                        .ok_or(name)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|name| {
+                    error!("Unknown scene {}", name);
+                    exit(1);
+                })
+        } else {
+            scenes.iter().collect()
         };
-    }
-}
-
-impl Vector {
-    fn zero() -> Self {
-        Vector {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
+        let results = selected
+            .iter()
+            .map(|(id, scene)| (id.as_str(), validate::validate_loaded(scene)))
+            .collect::<Vec<_>>();
+        println!("{}", validate::to_json(&results));
+        // Fail for use in scripts
+        if results.iter().any(|(_, problems)| !problems.is_empty()) {
+            exit(1);
         }
+        return;
     }
 
-    const fn from(a: f64, b: f64, c: f64) -> Self {
-        Vector { x: a, y: b, z: c }
-    }
-
-    const fn uniform(u: f64) -> Self {
-        Vector { x: u, y: u, z: u }
-    }
-
-    fn normalize(mut self) -> Self {
-        let m = self.magnitude();
-        self.x /= m;
-        self.y /= m;
-        self.z /= m;
-        return self;
-    }
+    let scenes = load_scenes().unwrap_or_else(|e| {
+        error!("Failed to load scenes: {}", e);
+        exit(1);
+    });
 
-    fn dot(&self, other: &Vector) -> f64 {
-        return self.x * other.x + self.y * other.y + self.z * other.z;
-    }
+    let print_usage = || {
+        println!(
+            "Run with:\ncargo run <samplesPerPixel = 4000> <y-resolution = 600> <scene = '{}'> [options]\n\
+            cargo run <scene> [options]    Use the scene's own samples and resolution\n\
+            cargo run validate [scene ..]  Check scenes for problems and print them as JSON\n\
+            cargo run worker [address = 127.0.0.1:7878]\n                               Render tiles for another process started with --workers.\n                               Workers don't authenticate, so only listen on other\n                               interfaces, like 0.0.0.0:7878, on a trusted network\n\n\
+            Options:\n  \
+            --width=<px>           Image width (default: 3:2 aspect ratio)\n  \
+            --aspect=<w>:<h>       Set the width from an aspect ratio like 16:9, 4:3, 1:1\n  \
+            --firefly-clamp=<max>  Clamp the radiance of each sample to <max>\n  \
+            --clamp-indirect-only  Don't clamp emitters seen directly by the camera\n  \
+            --max-depth=<n>        Maximum number of bounces (default: 12, or the scene's)\n  \
+            --min-bounces=<n>      Bounces before Russian roulette may end paths (default: 5,\n                         or the scene's)\n  \
+            --export-pbrt=<file>   Write the scene as a pbrt-v3 file instead of rendering\n  \
+            --integrator=<name>    path (default), ao, ao:<radius> for ambient occlusion, clay,\n                         or photon[:<radius>[:<photons>]] for caustics from a photon map\n  \
+            --tonemap=<operator>   One of clamp (default), reinhard, aces\n  \
+            --exposure=<stops>     Exposure adjustment applied before tonemapping\n  \
+            --filter=<name>[:<radius>]\n                         Pixel filter: box, tent (default), gaussian or blackman-harris,\n                         with a radius in pixels (default: 0.5, 1, 1.5 and 2)\n  \
+            --encoding=<name>      Transfer function applied after tonemapping: srgb (default),\n                         gamma2.2 or linear\n  \
+            --turntable=<frames>   Render <frames> images with the camera orbiting the scene\n  \
+            --turntable-step=<deg> Camera rotation between frames (default: 360 / frames)\n  \
+            --turntable-center=<x,y,z or object>\n                         Point or object (index or name) the camera orbits around\n                         (default: what --camera frames, or 0,0,0)\n  \
+            --animate              Render all frames of the scene's keyframe animation\n  \
+            --frames=<first>..<last>\n                         Render only these frames of the animation\n  \
+            --quiet                Only log warnings and errors, no render progress\n  \
+            --verbose              Also log debug messages, like loaded meshes and worker tiles\n  \
+            --seed=<n>             Render deterministically with this random seed\n  \
+            --inspect=<x>,<y>      Print color, first hit and sample paths of this pixel after rendering\n  \
+            --heatmap              Also write an image of the intersection tests per pixel\n  \
+            --profile              Write the time and intersection work of each image row to a CSV file\n  \
+            --path-lengths         Also write an image of the average path length per pixel\n                         and print a histogram of the path lengths\n  \
+            --transparent          Hide the background and write a .pam image with alpha channel\n  \
+            --material=<name>:color=<r,g,b>\n                         Change a shared material of the scene for all objects using it, also\n                         with emission=<r,g,b>. Colors here and in --background are\n                         linear, or sRGB when written as #rrggbb\n  \
+            --background=<r,g,b>[:<r,g,b>]\n                         Replace the environment with a color, or a gradient from bottom to top\n  \
+            --workers=<host:port,..>\n                         Render the image on worker processes instead of locally\n  \
+            --add=<primitive>[@<x,y,z>]\n                         Add a gray box:<x>,<y>,<z>, cylinder:<radius>,<height>[,<segments>],\n                         torus:<radius>,<tube radius>[,<segments>,<tube segments>] or\n                         plane:<width>,<depth>[,<subdivisions>], named after the descriptor\n  \
+            --hide=<i,j,..>        Exclude the objects with these indices or names from the render\n  \
+            --camera-invisible=<i,j,..>\n                         Hide objects from the camera but keep their shadows and light\n  \
+            --camera=<name>        Render from a camera bookmark of the scene, or use frame to fit\n                         the whole scene and frame:<i> to fit object <i> (index or name)\n  \
+            --clip=<x,y,z>:<nx,ny,nz>\n                         Cut away everything behind the plane through x,y,z with normal n\n  \
+            --clip-capped          Show the cut faces of closed objects\n\n\
+            Scenes: {}",
+            scenes.first().unwrap().id,
+            scenes.iter().enumerate().map(|(i, scene)| format!("{}: {}", i, scene.id)).collect::<Vec<_>>().join(", ")
+        );
+    };
 
-    fn cross(&self, other: &Vector) -> Vector {
-        return Vector {
-            x: self.y * other.z - self.z * other.y,
-            y: self.z * other.x - self.x * other.z,
-            z: self.x * other.y - self.y * other.x,
-        };
+    if args.get(1).map(String::as_str) == Some("worker") {
+        let address = args.get(2).map_or("127.0.0.1:7878", String::as_str);
+        let listener = TcpListener::bind(address).unwrap_or_else(|e| {
+            error!("Could not listen on {}: {}", address, e);
+            exit(1);
+        });
+        info!("Waiting for render jobs on {}", address);
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| {
+                info!("Rendering for {}", stream.peer_addr()?);
+                network::serve(stream, &scenes)
+            });
+            if let Err(e) = result {
+                error!("Render job failed: {}", e);
+            }
+        }
+        return;
     }
 
-    fn magnitude(&self) -> f64 {
-        return (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt();
+    // Workers get the same arguments, so they select the same scene and settings
+    let worker_args: Vec<String> = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--workers="))
+        .cloned()
+        .collect();
+    let (render_config, mut scene) = RenderConfig::with_scene(args, &scenes).unwrap_or_else(|| {
+        print_usage();
+        exit(1);
+    });
+    if let Err(message) = scene.apply_render_config(&render_config) {
+        error!("{}", message);
+        exit(1);
     }
-}
-
-struct Ray {
-    origin: Vector,
-    direction: Vector,
-}
-
-#[derive(Clone, Debug)]
-enum ReflectType {
-    Diffuse,
-    Specular,
-    Refract,
-}
-
-#[derive(Clone, Debug)]
-struct Material {
-    color: Vector,
-    emmission: Vector,
-    reflect_type: ReflectType,
-}
-
-#[derive(Clone, Debug)]
-pub struct SceneData {
-    id: String,
-    objects: Vec<SceneObjectData>,
-    camera: CameraData,
-}
-
-#[derive(Clone, Copy, Debug)]
-struct CameraData {
-    position: Vector,
-    /// normal to sensor plane
-    direction: Vector,
-    /// in meters
-    focal_length: f64,
-}
-
-#[derive(Clone, Debug)]
-struct SceneObjectData {
-    type_: SceneObject,
-    position: Vector,
-    material: Material,
-}
-
-impl SceneObjectData {
-    fn intersect(&self, ray: &Ray) -> IntersectResult {
-        return match &self.type_ {
-            SceneObject::Sphere { radius } => intersect_sphere(self.position, *radius, ray),
-
-            SceneObject::Mesh(mesh) => match intersect_sphere(
-                mesh.bounding_sphere.position + self.position,
-                mesh.bounding_sphere.radius,
-                ray,
-            ) {
-                IntersectResult::NoHit => IntersectResult::NoHit,
-                IntersectResult::Hit(_) => {
-                    for original_tri in mesh.triangles.iter() {
-                        let tri = original_tri.transformed(&self.position);
-                        let va_vb = tri.b - tri.a;
-                        let va_vc = tri.c - tri.a;
-
-                        let pvec = ray.direction.cross(&va_vc);
-                        let determinant = va_vb.dot(&pvec);
-
-                        if USE_CULLING {
-                            if determinant < 1e-4 {
-                                continue;
-                            }
-                        } else {
-                            if determinant.abs() < 1e-4 {
-                                continue;
-                            }
-                        }
-
-                        let inv_determinant = 1.0 / determinant;
-                        let tvec = ray.origin - tri.a;
-                        let u: f64 = tvec.dot(&pvec) * inv_determinant;
-                        if u < 0.0 || u > 1.0 {
-                            continue;
-                        }
-
-                        let qvec = tvec.cross(&va_vb);
-                        let v: f64 = ray.direction.dot(&qvec) * inv_determinant;
-                        if v < 0.0 || (u + v) > 1.0 {
-                            continue;
-                        }
-
-                        let distance: f64 = va_vb.dot(&qvec) * inv_determinant;
-                        let intersection = ray.direction * distance;
-                        let normal = va_vb.cross(&va_vc).normalize();
-
-                        return IntersectResult::Hit(Hit {
-                            distance,
-                            intersection,
-                            normal,
-                        });
-                    }
-                    return IntersectResult::NoHit;
-                }
-            },
-        };
+    let scene = &scene;
+
+    if let Some(path) = &render_config.export_pbrt {
+        let image_name = std::path::Path::new(path).with_extension("pfm");
+        let pbrt = export_pbrt::export_pbrt(
+            scene,
+            &render_config,
+            &image_name.file_name().unwrap().to_string_lossy(),
+        );
+        std::fs::write(path, pbrt).unwrap();
+        info!("Exported scene {} to {}", render_config.scene_id, path);
+        return;
     }
-}
-
-#[derive(Clone, Debug)]
-enum SceneObject {
-    Sphere { radius: f64 },
-    Mesh(Mesh),
-}
-
-#[derive(Clone, Debug)]
-struct StandaloneSphere {
-    position: Vector,
-    radius: f64,
-}
 
-fn intersect_sphere(position: Vector, radius: f64, ray: &Ray) -> IntersectResult {
-    let op: Vector = position - ray.origin;
-    let eps: f64 = 1e-4;
-    let b = op.dot(&ray.direction);
-    let mut det = b.powi(2) - op.dot(&op) + radius.powi(2);
-    if det < 0.0 {
-        return IntersectResult::NoHit;
-    } else {
-        det = det.sqrt();
-    }
-    let t = if b - det >= eps {
-        b - det
-    } else if b + det >= eps {
-        b + det
+    info!(
+        "Scene {} ({} objects, {} triangles), {} samples per pixel, {}x{} resolution{}",
+        render_config.scene_id,
+        scene.objects.len(),
+        scene.triangle_count(),
+        render_config.samples_per_pixel,
+        render_config.resolution_x(),
+        render_config.resolution_y,
+        if MOCK_RANDOM { " (mock random)" } else { "" }
+    );
+
+    // Create directory if it does not exist
+    std::fs::create_dir_all("out").unwrap();
+
+    let path_prefix = format!(
+        "out/{}-scene-{}-spp{}-res{}-",
+        chrono::Local::now().format(TIMESTAMP_FORMAT),
+        render_config.scene_id,
+        render_config.samples_per_pixel,
+        render_config.resolution_y,
+    );
+
+    if let Some(frame_count) = render_config.turntable_frames {
+        let step = render_config
+            .turntable_step
+            .unwrap_or(360.0 / frame_count as f64);
+        let center = scene
+            .turntable_center(&render_config)
+            .unwrap_or_else(|message| {
+                error!("{}", message);
+                exit(1);
+            });
+        render_sequence(0..=frame_count - 1, &path_prefix, &render_config, |frame| {
+            let mut frame_scene = scene.clone();
+            frame_scene.camera = scene
+                .camera
+                .orbited(center, (step * frame as f64).to_radians());
+            frame_scene
+        });
+    } else if render_config.animate {
+        let (first, last) = render_config
+            .frame_range
+            .or(scene.animation.frame_range())
+            .unwrap_or_else(|| {
+                error!("Scene {} has no animation", scene.id);
+                exit(1);
+            });
+        render_sequence(first..=last, &path_prefix, &render_config, |frame| {
+            scene.animation.evaluate(scene, frame)
+        });
     } else {
-        return IntersectResult::NoHit;
-    };
-
-    let xmin = ray.origin + ray.direction * t;
-    let nmin = (xmin - position).normalize();
-
-    return IntersectResult::Hit(Hit {
-        distance: t,
-        intersection: xmin,
-        normal: nmin,
-    });
-}
-
-#[derive(Clone, Debug)]
-struct Mesh {
-    triangles: Vec<Triangle>,
-    bounding_sphere: StandaloneSphere,
-}
-
-#[derive(Clone, Debug)]
-struct Triangle {
-    a: Vector,
-    b: Vector,
-    c: Vector,
-}
-
-impl Triangle {
-    fn transformed(&self, v: &Vector) -> Triangle {
-        Triangle {
-            a: self.a + *v,
-            b: self.b + *v,
-            c: self.c + *v,
+        let mut profile = None;
+        let (pixels, alpha) = if render_config.profile {
+            let (pixels, alpha, rows) = render_profiled(scene, &render_config);
+            profile = Some(rows);
+            (pixels, alpha)
+        } else if render_config.workers.is_empty() {
+            render_with_alpha(scene, &render_config)
+        } else {
+            network::render_distributed(&worker_args, &render_config.workers, &render_config)
+                .unwrap_or_else(|e| {
+                    error!("Distributed render failed: {}", e);
+                    exit(1);
+                })
+        };
+        write_image(&pixels, &alpha, &render_config, &path_prefix, time_start);
+        if let Some(profile) = profile {
+            print_profile(scene, &profile, &path_prefix);
         }
-    }
-}
-
-#[derive(PartialEq, Debug)]
-struct Hit {
-    distance: f64,
-    intersection: Vector,
-    normal: Vector,
-}
-
-enum IntersectResult {
-    NoHit,
-    Hit(Hit),
-}
 
-#[derive(PartialEq, Debug)]
-enum SceneIntersectResult {
-    NoHit,
-    Hit { object_id: usize, hit: Hit },
-}
+        if let Some((x, y)) = render_config.inspect {
+            print!("{}", inspect_pixel(scene, &render_config, &pixels, x, y));
+        }
 
-fn intersect_scene(ray: &Ray, scene_objects: &Vec<SceneObjectData>) -> SceneIntersectResult {
-    let mut min_intersect: SceneIntersectResult = SceneIntersectResult::NoHit;
-
-    for i in (0..scene_objects.len()).rev() {
-        let scene_object = &scene_objects[i];
-        let intersect = scene_object.intersect(ray);
-        match (intersect, &min_intersect) {
-            (IntersectResult::NoHit, _) => (),
-            (IntersectResult::Hit(new_hit), SceneIntersectResult::NoHit) => {
-                min_intersect = SceneIntersectResult::Hit {
-                    object_id: i,
-                    hit: new_hit,
-                };
+        if render_config.heatmap || render_config.path_lengths {
+            info!("Collecting ray statistics ...");
+            let stats = render_heatmap(scene, &render_config);
+            if render_config.heatmap {
+                print_heatmap(scene, &stats, &render_config, &path_prefix);
             }
-            (IntersectResult::Hit(new_hit), SceneIntersectResult::Hit { hit, .. }) => {
-                if new_hit.distance < hit.distance {
-                    min_intersect = SceneIntersectResult::Hit {
-                        object_id: i,
-                        hit: new_hit,
-                    };
-                }
+            if render_config.path_lengths {
+                print_path_lengths(scene, &stats, &render_config, &path_prefix);
             }
         }
     }
-    return min_intersect;
-}
-
-const MAX_DEPTH: usize = 12;
-fn radiance(ray: &Ray, depth: usize, scene_objects: &Vec<SceneObjectData>) -> Vector {
-    return match intersect_scene(&ray, scene_objects) {
-        SceneIntersectResult::NoHit => Vector::zero(),
-        SceneIntersectResult::Hit { object_id, hit } => {
-            let object = &scene_objects[object_id];
-            let mut color: Vector = object.material.color;
-            let max_reflection = color.x.max(color.y.max(color.z));
-            let normal_towards_ray = if hit.normal.dot(&ray.direction) < 0.0 {
-                hit.normal
-            } else {
-                hit.normal * -1.0
-            };
-
-            //--- Russian Roulette Ray termination
-            let new_depth = depth + 1;
-            if new_depth > 5 {
-                if rand01() < max_reflection && new_depth < MAX_DEPTH {
-                    color = color * (1.0 / max_reflection);
-                } else {
-                    return object.material.emmission;
-                }
-            }
-
-            object.material.emmission
-                + match object.material.reflect_type {
-                    ReflectType::Diffuse => {
-                        // Ideal DIFFUSE reflection
-
-                        // cosinus-weighted importance sampling
-                        let r1: f64 = 2.0 * PI * rand01();
-                        let r2: f64 = rand01();
-                        let r2s: f64 = r2.sqrt();
-                        let w: Vector = normal_towards_ray;
-                        let u = (if w.x.abs() > 0.1 {
-                            Vector::from(0.0, 1.0, 0.0)
-                        } else {
-                            Vector::from(1.0, 0.0, 0.0)
-                        })
-                        .cross(&w)
-                        .normalize();
-                        let v = w.cross(&u);
-                        let d = (u * r1.cos() * r2s + v * r1.sin() * r2s + w * (1.0 - r2).sqrt())
-                            .normalize();
-
-                        color
-                            * radiance(
-                                &Ray {
-                                    origin: hit.intersection,
-                                    direction: d,
-                                },
-                                new_depth,
-                                scene_objects,
-                            )
-                    }
-                    ReflectType::Specular => {
-                        // Ideal SPECULAR reflection
-                        color
-                            * radiance(
-                                &Ray {
-                                    origin: hit.intersection,
-                                    direction: ray.direction
-                                        - hit.normal * 2.0 * hit.normal.dot(&ray.direction),
-                                },
-                                new_depth,
-                                scene_objects,
-                            )
-                    }
-                    ReflectType::Refract => {
-                        // Ideal dielectric REFRACTION
-                        let refl_ray = Ray {
-                            origin: hit.intersection,
-                            direction: ray.direction
-                                - hit.normal * 2.0 * hit.normal.dot(&ray.direction),
-                        };
-                        let into = hit.normal.dot(&normal_towards_ray) > 0.0; // Ray from outside going in?
-                        let nc = 1.0; // Index of refraction air
-                        let nt = 1.5; // Index of refraction glass
-                        let nnt: f64 = if into { nc / nt } else { nt / nc };
-                        let ddn = ray.direction.dot(&normal_towards_ray);
-                        let cos2t = 1.0 - nnt.powi(2) * (1.0 - ddn.powi(2));
-
-                        if cos2t < 0.0 {
-                            color * radiance(&refl_ray, new_depth, scene_objects)
-                        } else {
-                            let tdir = (ray.direction * nnt
-                                - hit.normal
-                                    * (if into { 1.0 } else { -1.0 } * (ddn * nnt + cos2t.sqrt())))
-                            .normalize();
-                            let a = nt - nc;
-                            let b = nt + nc;
-                            let r0 = a * a / (b * b);
-                            let c = 1.0 - (if into { -ddn } else { tdir.dot(&hit.normal) });
-                            let re = r0 + (1.0 - r0) * c.powi(5);
-                            let tr = 1.0 - re;
-                            let p = 0.25 + 0.5 * re;
-                            let rp = re / p;
-                            let tp = tr / (1.0 - p);
-
-                            if new_depth > 2 {
-                                if rand01() < p {
-                                    color * radiance(&refl_ray, new_depth, scene_objects) * rp
-                                } else {
-                                    color
-                                        * radiance(
-                                            &Ray {
-                                                origin: hit.intersection,
-                                                direction: tdir,
-                                            },
-                                            new_depth,
-                                            scene_objects,
-                                        )
-                                        * tp
-                                }
-                            } else {
-                                color
-                                    * (radiance(&refl_ray, new_depth, scene_objects) * re
-                                        + radiance(
-                                            &Ray {
-                                                origin: hit.intersection,
-                                                direction: tdir,
-                                            },
-                                            new_depth,
-                                            scene_objects,
-                                        ) * tr)
-                            }
-                        }
-                    }
-                }
-        }
-    };
 }
 
-struct RenderConfig {
-    samples_per_pixel: usize,
-    resolution_y: usize,
-    scene_id: SceneId,
-}
-
-#[derive(Clone, Debug)]
-enum SceneId {
-    Int(usize),
-    String(String),
-}
-
-impl Display for SceneId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            SceneId::Int(i) => f.write_str(&i.to_string()),
-            SceneId::String(s) => f.write_str(s),
-        }
+/// Writes the heatmap of the diagnostic pass next to the image and prints
+/// where the intersection tests went.
+fn print_heatmap(
+    scene: &SceneData,
+    stats: &[RayStats],
+    render_config: &RenderConfig,
+    path_prefix: &str,
+) {
+    let path = format!("{}heatmap.ppm", path_prefix);
+    write_heatmap(&path, stats, render_config).unwrap();
+
+    let samples = (stats.len() * render_config.samples_per_pixel) as f64;
+    let rays: u64 = stats.iter().map(|s| s.rays).sum();
+    let tests: u64 = stats.iter().map(|s| s.primitive_tests).sum();
+    info!(
+        "{:.2} rays and {:.1} primitive tests per sample, heatmap written to {}",
+        rays as f64 / samples,
+        tests as f64 / samples,
+        path
+    );
+    let mut object_tests: Vec<(usize, u64)> = (0..scene.objects.len())
+        .map(|i| (i, stats.iter().map(|s| s.object_tests[i]).sum()))
+        .collect();
+    object_tests.sort_by_key(|(_, tests)| std::cmp::Reverse(*tests));
+    for (i, object_tests) in object_tests {
+        println!(
+            "  object {:<20} {:5.1}% of tests",
+            format!("{}:", scene.object_label(i)),
+            100.0 * object_tests as f64 / tests.max(1) as f64
+        );
     }
 }
 
-impl RenderConfig {
-    fn from(args: Vec<String>) -> Option<Self> {
-        return match args.len() {
-            4 => {
-                let scene_id_int: Option<usize> = args.get(3)?.parse().ok();
-                let scene_id = match scene_id_int {
-                    Some(int) => SceneId::Int(int),
-                    None => SceneId::String(args.get(3)?.clone()),
-                };
-                Some(RenderConfig {
-                    samples_per_pixel: args.get(1)?.parse().ok()?,
-                    resolution_y: args.get(2)?.parse().ok()?,
-                    scene_id,
-                })
-            }
-            1 => Some(RenderConfig::default()),
-            _ => None,
-        };
+/// Writes the profile of the render next to the image and prints where the
+/// time went.
+fn print_profile(scene: &SceneData, profile: &[TileProfile], path_prefix: &str) {
+    let path = format!("{}profile.csv", path_prefix);
+    write_profile(&path, profile).unwrap();
+    info!("Profile written to {}", path);
+
+    let seconds = |time: Duration| time.as_secs_f64();
+    let total: f64 = profile.iter().map(|row| seconds(row.time)).sum();
+    let intersection: f64 = profile
+        .iter()
+        .map(|row| seconds(row.stats.intersection_time))
+        .sum();
+    let rays: u64 = profile.iter().map(|row| row.stats.rays).sum();
+    let tests: u64 = profile.iter().map(|row| row.stats.primitive_tests).sum();
+    println!(
+        "  {:.2}s rendering rows, {:.1}% finding hits, {:.1}% shading",
+        total,
+        100.0 * intersection / total.max(1e-9),
+        100.0 * (total - intersection) / total.max(1e-9)
+    );
+    println!(
+        "  {} rays, {:.1} primitive tests per ray",
+        rays,
+        tests as f64 / rays.max(1) as f64
+    );
+
+    let threads = profile.iter().map(|row| row.thread).max().unwrap_or(0) + 1;
+    for thread in 0..threads {
+        let rows: Vec<&TileProfile> = profile.iter().filter(|row| row.thread == thread).collect();
+        let time: f64 = rows.iter().map(|row| seconds(row.time)).sum();
+        println!("  thread {}: {} rows, {:.2}s", thread, rows.len(), time);
     }
 
-    fn default() -> Self {
-        Self {
-            samples_per_pixel: 4000,
-            resolution_y: 600,
-            scene_id: SceneId::Int(0),
+    let mut slowest: Vec<&TileProfile> = profile.iter().collect();
+    slowest.sort_by_key(|row| std::cmp::Reverse(row.time));
+    let slowest: Vec<String> = slowest
+        .iter()
+        .take(3)
+        .map(|row| format!("{} ({:.3}s)", row.row, seconds(row.time)))
+        .collect();
+    println!("  slowest rows: {}", slowest.join(", "));
+
+    let mut object_tests: Vec<(usize, u64)> = (0..scene.objects.len())
+        .map(|i| (i, profile.iter().map(|row| row.stats.object_tests[i]).sum()))
+        .collect();
+    object_tests.sort_by_key(|(_, tests)| std::cmp::Reverse(*tests));
+    let busiest: Vec<String> = object_tests
+        .iter()
+        .take(3)
+        .map(|&(i, object_tests)| {
+            format!(
+                "{} {:.1}%",
+                scene.object_label(i),
+                100.0 * object_tests as f64 / tests.max(1) as f64
+            )
+        })
+        .collect();
+    println!("  most tested objects: {}", busiest.join(", "));
+}
+
+/// Writes the path length image and histogram of the diagnostic pass next to
+/// the image and prints the histogram.
+fn print_path_lengths(
+    scene: &SceneData,
+    stats: &[RayStats],
+    render_config: &RenderConfig,
+    path_prefix: &str,
+) {
+    let path = format!("{}path-lengths.ppm", path_prefix);
+    write_path_length_map(&path, stats, scene, render_config).unwrap();
+
+    let mut histogram: Vec<u64> = Vec::new();
+    for pixel in stats {
+        if histogram.len() < pixel.path_lengths.len() {
+            histogram.resize(pixel.path_lengths.len(), 0);
+        }
+        for (total, count) in histogram.iter_mut().zip(&pixel.path_lengths) {
+            *total += count;
         }
     }
-}
-
-fn main() {
-    let time_start = std::time::Instant::now();
-
-    let scenes = load_scenes();
-
-    let print_usage = || {
-        println!(
-            "Run with:\ncargo run <samplesPerPixel = 4000> <y-resolution = 600> <scene = '{}'>\n\nScenes: {}",
-            scenes.iter().next().unwrap().id,
-            scenes.iter().enumerate().map(|(i, scene)| format!("{}: {}", i, scene.id)).collect::<Vec<_>>().join(", ")
+    let total = RayStats {
+        path_lengths: histogram,
+        ..RayStats::default()
+    };
+    let Some(average) = total.average_path_length() else {
+        info!(
+            "No paths traced by the {:?} integrator",
+            render_config.integrator
         );
+        return;
     };
+    info!("{:.2} rays per path, image written to {}", average, path);
 
-    let maybe_render_config = RenderConfig::from(std::env::args().collect());
-    match maybe_render_config {
-        None => {
-            print_usage();
-            exit(1);
-        }
-        Some(render_config) => {
-            let scene: &SceneData = match render_config.scene_id.clone() {
-                SceneId::Int(i) => scenes.get(i),
-                SceneId::String(s) => scenes.iter().find(|scene| scene.id == s.as_str()),
-            }
-            .unwrap_or_else(|| {
-                print_usage();
-                exit(1);
-            });
-            let scene_objects = &scene.objects;
-
-            //-- setup sensor
-            let sensor_origin: Vector = scene.camera.position;
-            let sensor_view_direction: Vector = scene.camera.direction.normalize();
-            let sensor_width: f64 = 0.036;
-            let sensor_height: f64 = sensor_width * 2.0 / 3.0;
-            let focal_length: f64 = scene.camera.focal_length;
-            // lens center (pinhole)
-            let lens_center = sensor_origin + sensor_view_direction * focal_length;
-
-            //-- orthogonal axes spanning the sensor plane
-            let su: Vector = sensor_view_direction
-                .cross(&if sensor_view_direction.y.abs() < 0.9 {
-                    Vector::from(0.0, 1.0, 0.0)
-                } else {
-                    Vector::from(0.0, 0.0, 1.0)
-                })
-                .normalize();
-            let sv: Vector = su.cross(&sensor_view_direction);
-
-            let resy = render_config.resolution_y;
-            let resx: usize = resy * 3 / 2;
-            let grid_size = resx * resy;
-
-            println!(
-                "Scene {} ({} objects), {} samples per pixel, {}x{} resolution{}",
-                render_config.scene_id,
-                scene_objects.len(),
-                render_config.samples_per_pixel,
-                render_config.resolution_y * 3 / 2,
-                render_config.resolution_y,
-                if MOCK_RANDOM { " (mock random)" } else { "" }
-            );
-
-            let last_progress_print_time = atomic::AtomicU64::new(0);
-            let max_time_between_progress_prints = 1000;
-            let processed_pixel_count = atomic::AtomicUsize::new(0);
-
-            let print_progress = || {
-                fn fmt(d: std::time::Duration) -> String {
-                    let seconds = d.as_secs() % 60;
-                    let minutes = (d.as_secs() / 60) % 60;
-                    let hours = (d.as_secs() / 60) / 60;
-                    if hours == 0 {
-                        return format!("{}m:{:0>2}s", minutes, seconds);
-                    }
-                    format!("{}:{:0>2}:{:0>2}", hours, minutes, seconds)
-                }
-                let processed_percentage = processed_pixel_count.load(atomic::Ordering::Relaxed)
-                    as f64
-                    / (grid_size) as f64;
-                let elapsed = time_start.elapsed();
-                print!(
-                    "\rRendering ... {:3.1}% ({} / {})",
-                    100.0 * processed_percentage,
-                    fmt(elapsed),
-                    fmt(Duration::from_secs(
-                        (elapsed.as_secs() as f64 * (1.0 / processed_percentage)) as u64
-                    ))
-                );
-                std::io::stdout().flush().unwrap();
-                last_progress_print_time.store(
-                    time_start.elapsed().as_millis() as u64,
-                    atomic::Ordering::Relaxed,
-                );
-            };
-
-            print_progress();
-
-            let fun = |pixel_index| {
-                if last_progress_print_time.load(atomic::Ordering::Relaxed)
-                    + max_time_between_progress_prints
-                    < time_start.elapsed().as_millis() as u64
-                {
-                    print_progress();
-                }
-
-                let y = resy - 1 - pixel_index / resx;
-                let x = pixel_index % resx;
-
-                let mut radiance_v: Vector = Vector::zero();
-
-                for s in 0..render_config.samples_per_pixel {
-                    // map to 2x2 subpixel rows and cols
-                    let ysub: f64 = ((s / 2) % 2) as f64;
-                    let xsub: f64 = (s % 2) as f64;
-
-                    // sample sensor subpixel in [-1,1]
-                    let r1: f64 = 2.0 * rand01();
-                    let r2: f64 = 2.0 * rand01();
-                    let xfilter: f64 = if r1 < 1.0 {
-                        // TODO not sure what this is
-                        r1.sqrt() - 1.0
-                    } else {
-                        1.0 - (2.0 - r1).sqrt()
-                    };
-                    let yfilter: f64 = if r2 < 1.0 {
-                        r2.sqrt() - 1.0
-                    } else {
-                        1.0 - (2.0 - r2).sqrt()
-                    };
-
-                    // x and y sample position on sensor plane
-                    let sx: f64 = ((x as f64 + 0.5 * (0.5 + xsub + xfilter)) / resx as f64 - 0.5)
-                        * sensor_width;
-                    let sy: f64 = ((y as f64 + 0.5 * (0.5 + ysub + yfilter)) / resy as f64 - 0.5)
-                        * sensor_height;
-
-                    // 3d sample position on sensor
-                    let sensor_pos = sensor_origin + su * sx + sv * sy;
-                    let ray_direction = (lens_center - sensor_pos).normalize();
-                    // ray through pinhole
-                    let ray = Ray {
-                        origin: lens_center,
-                        direction: ray_direction,
-                    };
-
-                    // evaluate radiance from this ray and accumulate
-                    radiance_v = radiance_v + radiance(&ray, 0, &scene_objects);
-                }
-                // normalize radiance by number of samples
-                radiance_v = radiance_v / render_config.samples_per_pixel as f64;
-                processed_pixel_count.fetch_add(1, atomic::Ordering::Relaxed);
-
-                Vector::from(
-                    radiance_v.x.clamp(0.0, 1.0),
-                    radiance_v.y.clamp(0.0, 1.0),
-                    radiance_v.z.clamp(0.0, 1.0),
-                )
-            };
-            let pixels: Vec<Vector> = if MOCK_RANDOM {
-                (0..grid_size).into_iter().map(fun).collect()
+    let paths: u64 = total.path_lengths.iter().sum();
+    let max_depth = scene.render_settings.max_depth.unwrap_or(MAX_DEPTH);
+    let mut csv = "rays,paths\n".to_owned();
+    for (length, &count) in total.path_lengths.iter().enumerate().skip(1) {
+        let share = count as f64 / paths as f64;
+        println!(
+            "  {:>3} rays {:5.1}% {}{}",
+            length,
+            100.0 * share,
+            "#".repeat((share * 50.0).round() as usize),
+            if length == max_depth {
+                " (max depth)"
             } else {
-                // Use rayon to parallelize rendering
-                (0..grid_size).into_par_iter().map(fun).collect()
-            };
-
-            print_progress();
-            println!();
-
-            // Create directory if it does not exist
-            std::fs::create_dir_all("out").unwrap();
-
-            // Write .ppm file
-            let path = format!(
-                "out/{}-scene-{}-spp{}-res{}-.ppm",
-                chrono::Local::now().format("%Y-%m-%d_%H:%M:%S").to_string(),
-                render_config.scene_id,
-                render_config.samples_per_pixel,
-                render_config.resolution_y,
-            );
-            let mut file = std::fs::File::create(path.clone()).unwrap();
-            file.write_all(b"P3\n").unwrap();
-            file.write_all(
-                format!(
-                    "# samplesPerPixel: {}, resolution_y: {}, scene_id: {}\n",
-                    render_config.samples_per_pixel,
-                    render_config.resolution_y,
-                    render_config.scene_id
-                )
-                .as_bytes(),
-            )
-            .unwrap();
-            file.write_all(
-                format!(
-                    "# rendering time: {} s\n",
-                    std::time::Instant::now()
-                        .duration_since(time_start)
-                        .as_secs()
-                )
-                .as_bytes(),
-            )
-            .unwrap();
-            file.write_all(format!("{} {}\n{}\n", resx, resy, 255).as_bytes())
-                .unwrap();
-            for pixel in pixels.iter().rev() {
-                file.write_all(
-                    format!(
-                        "{} {} {} ",
-                        to_int_with_gamma_correction(pixel.x),
-                        to_int_with_gamma_correction(pixel.y),
-                        to_int_with_gamma_correction(pixel.z)
-                    )
-                    .as_bytes(),
-                )
-                .unwrap();
+                ""
             }
+        );
+        csv.push_str(&format!("{},{}\n", length, count));
+    }
+    let path = format!("{}path-lengths.csv", path_prefix);
+    std::fs::write(&path, csv).unwrap();
+    info!("Histogram written to {}", path);
+}
+
+/// Renders numbered frames into the directory `path_prefix`.
+fn render_sequence(
+    frames: RangeInclusive<usize>,
+    path_prefix: &str,
+    render_config: &RenderConfig,
+    scene_for_frame: impl Fn(usize) -> SceneData,
+) {
+    std::fs::create_dir_all(path_prefix).unwrap();
+    let (first, last) = (*frames.start(), *frames.end());
+    for frame in frames {
+        let frame_start = std::time::Instant::now();
+        info!(
+            "Frame {} ({} / {})",
+            frame,
+            frame - first + 1,
+            last - first + 1
+        );
+        let (pixels, alpha) = render_with_alpha(&scene_for_frame(frame), render_config);
+        write_image(
+            &pixels,
+            &alpha,
+            render_config,
+            &format!("{}/frame-{:04}", path_prefix, frame),
+            frame_start,
+        );
+    }
+}
 
-            // Create symlink for easy access to newest image
-            std::fs::remove_file("latest.ppm").unwrap_or_default();
-            match std::os::unix::fs::symlink(path.clone(), "latest.ppm") {
-                Ok(_) => (),
-                Err(_) => {
-                    println!(
-                        "Could not create symlink to latest image. You can find it at {}",
-                        path
-                    );
-                }
-            }
+/// Writes the image to `path_prefix` with the .ppm extension, or .pam when the
+/// background is transparent.
+fn write_image(
+    pixels: &[Vector],
+    alpha: &[f64],
+    render_config: &RenderConfig,
+    path_prefix: &str,
+    time_start: std::time::Instant,
+) {
+    let path = if render_config.transparent {
+        let path = format!("{}.pam", path_prefix);
+        write_pam(&path, pixels, alpha, render_config, time_start.elapsed()).unwrap();
+        path
+    } else {
+        let path = format!("{}.ppm", path_prefix);
+        write_ppm(&path, pixels, render_config, time_start.elapsed()).unwrap();
+        path
+    };
+    info!("Image written to {}", path);
+    link_latest(&path);
+}
+
+/// Create symlink for easy access to newest image
+fn link_latest(path: &str) {
+    std::fs::remove_file("latest.ppm").unwrap_or_default();
+    std::fs::remove_file("latest.pam").unwrap_or_default();
+    let extension = std::path::Path::new(path).extension().unwrap();
+    let link = format!("latest.{}", extension.to_string_lossy());
+    match point_to(path, &link) {
+        Ok(_) => (),
+        Err(_) => {
+            warn!(
+                "Could not create link to latest image. You can find it at {}",
+                path
+            );
         }
     }
 }
+
+#[cfg(unix)]
+fn point_to(path: &str, link: &str) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(path, link)
+}
+
+/// Symlinks need extra privileges on Windows, so use a hard link, or a copy
+/// if `path` is on another drive
+#[cfg(not(unix))]
+fn point_to(path: &str, link: &str) -> std::io::Result<()> {
+    std::fs::hard_link(path, link).or_else(|_| std::fs::copy(path, link).map(|_| ()))
+}
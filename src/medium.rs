@@ -0,0 +1,52 @@
+use std::f64::consts::PI;
+
+use crate::Vector;
+
+/// Homogeneous participating medium filling the whole scene, like fog or haze.
+/// Coefficients are per meter and the same for all wavelengths.
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    /// Absorption coefficient
+    pub sigma_a: f64,
+    /// Scattering coefficient
+    pub sigma_s: f64,
+    /// Henyey-Greenstein asymmetry, from -1 (backward) over 0 (isotropic)
+    /// to 1 (forward scattering)
+    pub g: f64,
+}
+
+impl Medium {
+    pub fn sigma_t(&self) -> f64 {
+        return self.sigma_a + self.sigma_s;
+    }
+
+    /// Distance to the next interaction, sampled proportional to transmittance.
+    pub fn sample_distance(&self, r: f64) -> f64 {
+        return -(1.0 - r).ln() / self.sigma_t();
+    }
+
+    /// Samples a new direction for a ray travelling in `direction` from the
+    /// Henyey-Greenstein phase function.
+    pub fn sample_phase(&self, direction: Vector, r1: f64, r2: f64) -> Vector {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * r1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+
+        let w = direction;
+        let u = (if w.x.abs() > 0.1 {
+            Vector::from(0.0, 1.0, 0.0)
+        } else {
+            Vector::from(1.0, 0.0, 0.0)
+        })
+        .cross(&w)
+        .normalize();
+        let v = w.cross(&u);
+        return (u * phi.cos() * sin_theta + v * phi.sin() * sin_theta + w * cos_theta).normalize();
+    }
+}
@@ -0,0 +1,704 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::BuildHasherDefault,
+    io::{Error, ErrorKind},
+};
+
+use crate::{
+    hash::CellHasher, min_hit_distance, Hit, IntersectResult, Ray, StandaloneSphere, Vector,
+    USE_CULLING,
+};
+
+/// Number of triangles intersected at once by the SIMD path.
+/// Triangle storage is padded to a multiple of this.
+const BATCH_SIZE: usize = 4;
+
+/// Start of the binary mesh format, including its version
+const BINARY_MAGIC: &[u8; 8] = b"MESHBIN1";
+
+/// Axis pointing up in a mesh file. The renderer uses Y-up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpAxis {
+    Y,
+    /// Common for CAD and 3D-print models
+    Z,
+}
+
+impl UpAxis {
+    pub fn to_y_up(&self, v: Vector) -> Vector {
+        return match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Vector::from(v.x, v.z, -v.y),
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    pub a: Vector,
+    pub b: Vector,
+    pub c: Vector,
+}
+
+impl Triangle {
+    pub fn area(&self) -> f64 {
+        return (self.b - self.a).cross(&(self.c - self.a)).magnitude() * 0.5;
+    }
+}
+
+/// What `Mesh::sanitized` changed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshCleanup {
+    /// Triangle corners moved onto a nearby vertex
+    pub welded_corners: usize,
+    /// Triangles removed for having zero area, often only after welding
+    pub degenerate_triangles: usize,
+    /// Triangles removed for having NaN or infinite coordinates
+    pub non_finite_triangles: usize,
+}
+
+impl MeshCleanup {
+    pub fn is_empty(&self) -> bool {
+        return *self == MeshCleanup::default();
+    }
+}
+
+impl Display for MeshCleanup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "welded {} triangle corners, removed {} degenerate and {} non-finite triangles",
+            self.welded_corners, self.degenerate_triangles, self.non_finite_triangles
+        )
+    }
+}
+
+/// Triangle vertices in structure-of-arrays layout, so that several
+/// triangles can be loaded into SIMD lanes at once.
+#[derive(Clone, Debug, Default)]
+struct TriangleSoa {
+    ax: Vec<f64>,
+    ay: Vec<f64>,
+    az: Vec<f64>,
+    bx: Vec<f64>,
+    by: Vec<f64>,
+    bz: Vec<f64>,
+    cx: Vec<f64>,
+    cy: Vec<f64>,
+    cz: Vec<f64>,
+}
+
+impl TriangleSoa {
+    fn push(&mut self, tri: &Triangle) {
+        self.ax.push(tri.a.x);
+        self.ay.push(tri.a.y);
+        self.az.push(tri.a.z);
+        self.bx.push(tri.b.x);
+        self.by.push(tri.b.y);
+        self.bz.push(tri.b.z);
+        self.cx.push(tri.c.x);
+        self.cy.push(tri.c.y);
+        self.cz.push(tri.c.z);
+    }
+
+    fn get(&self, i: usize) -> Triangle {
+        Triangle {
+            a: Vector::from(self.ax[i], self.ay[i], self.az[i]),
+            b: Vector::from(self.bx[i], self.by[i], self.bz[i]),
+            c: Vector::from(self.cx[i], self.cy[i], self.cz[i]),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    // Boxed to keep `SceneObject` small
+    triangles: Box<TriangleSoa>,
+    triangle_count: usize,
+    pub(crate) bounding_sphere: StandaloneSphere,
+    /// Cumulative triangle areas, for sampling points uniformly on the surface
+    area_cdf: Vec<f64>,
+    /// Colors of the three vertices of each triangle, or empty
+    vertex_colors: Vec<[Vector; 3]>,
+}
+
+impl Mesh {
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        let mut min_vert = Vector::uniform(f64::INFINITY);
+        let mut max_vert = Vector::uniform(f64::NEG_INFINITY);
+        let mut soa = TriangleSoa::default();
+        for tri in triangles {
+            for vert in [tri.a, tri.b, tri.c] {
+                min_vert = Vector::from(
+                    min_vert.x.min(vert.x),
+                    min_vert.y.min(vert.y),
+                    min_vert.z.min(vert.z),
+                );
+                max_vert = Vector::from(
+                    max_vert.x.max(vert.x),
+                    max_vert.y.max(vert.y),
+                    max_vert.z.max(vert.z),
+                );
+            }
+            soa.push(tri);
+        }
+
+        // Pad with degenerate triangles, which never produce a hit
+        let degenerate = Triangle {
+            a: Vector::zero(),
+            b: Vector::zero(),
+            c: Vector::zero(),
+        };
+        while soa.ax.len() % BATCH_SIZE != 0 {
+            soa.push(&degenerate);
+        }
+
+        let mut area_cdf = Vec::with_capacity(triangles.len());
+        let mut area = 0.0;
+        for tri in triangles {
+            area += tri.area();
+            area_cdf.push(area);
+        }
+
+        let center = (min_vert + max_vert) * 0.5;
+        return Mesh {
+            triangles: Box::new(soa),
+            triangle_count: triangles.len(),
+            bounding_sphere: StandaloneSphere {
+                position: center,
+                radius: (max_vert - center).magnitude(),
+            },
+            area_cdf,
+            vertex_colors: Vec::new(),
+        };
+    }
+
+    /// Adds a color for each vertex of each triangle, which materials can
+    /// use as albedo.
+    pub fn with_vertex_colors(mut self, colors: Vec<[Vector; 3]>) -> Self {
+        assert_eq!(colors.len(), self.triangle_count);
+        self.vertex_colors = colors;
+        return self;
+    }
+
+    /// Copy of the mesh with `f` applied to every vertex. Triangles of a
+    /// mirrored mesh are flipped to keep their winding.
+    pub fn transformed(&self, f: impl Fn(Vector) -> Vector) -> Self {
+        let mut triangles = Vec::with_capacity(self.triangle_count);
+        let mut colors = self.vertex_colors.clone();
+        for i in 0..self.triangle_count {
+            let tri = self.triangle(i);
+            let (a, b, c) = (f(tri.a), f(tri.b), f(tri.c));
+            // The moved normal points away from the moved offset along the
+            // original normal exactly when `f` mirrors
+            let normal = (tri.b - tri.a).cross(&(tri.c - tri.a));
+            let mirrored = (b - a).cross(&(c - a)).dot(&(f(tri.a + normal) - a)) < 0.0;
+            if mirrored {
+                triangles.push(Triangle { a, b: c, c: b });
+                if let Some(color) = colors.get_mut(i) {
+                    color.swap(1, 2);
+                }
+            } else {
+                triangles.push(Triangle { a, b, c });
+            }
+        }
+        let mesh = Mesh::from_triangles(&triangles);
+        return if colors.is_empty() {
+            mesh
+        } else {
+            mesh.with_vertex_colors(colors)
+        };
+    }
+
+    /// Copy of the mesh that intersects reliably: triangles with non-finite
+    /// coordinates are removed, vertices closer than `weld_distance` are moved
+    /// onto the first of them, and triangles left without area are removed.
+    pub fn sanitized(&self, weld_distance: f64) -> (Self, MeshCleanup) {
+        let mut cleanup = MeshCleanup::default();
+        let finite = |v: Vector| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+        // Vertices by their bits, for the common exact duplicates, and by
+        // grid cells twice as large as the weld distance, so that the
+        // vertices within reach are in at most 8 cells
+        // Closed meshes have about half as many vertices as triangles
+        let mut exact: HashMap<[u64; 3], Vector, BuildHasherDefault<CellHasher>> =
+            HashMap::with_capacity_and_hasher(self.triangle_count / 2, Default::default());
+        let mut cells: HashMap<[i64; 3], Vec<Vector>, BuildHasherDefault<CellHasher>> =
+            HashMap::default();
+        let cell = |v: Vector| {
+            let index = |x: f64| (x / (2.0 * weld_distance)).floor() as i64;
+            return [index(v.x), index(v.y), index(v.z)];
+        };
+        let mut weld = |v: Vector| -> Vector {
+            let bits = [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+            if let Some(&welded) = exact.get(&bits) {
+                return welded;
+            }
+            let mut welded = v;
+            if weld_distance > 0.0 {
+                let [x0, y0, z0] = cell(v - Vector::uniform(weld_distance));
+                let [x1, y1, z1] = cell(v + Vector::uniform(weld_distance));
+                let nearby = (x0..=x1)
+                    .flat_map(|x| (y0..=y1).flat_map(move |y| (z0..=z1).map(move |z| [x, y, z])))
+                    .filter_map(|key| cells.get(&key))
+                    .flatten()
+                    .find(|&&other| (other - v).magnitude() <= weld_distance);
+                match nearby {
+                    Some(&other) => welded = other,
+                    None => cells.entry(cell(v)).or_default().push(v),
+                }
+            }
+            exact.insert(bits, welded);
+            return welded;
+        };
+
+        let mut triangles = Vec::with_capacity(self.triangle_count);
+        let mut colors = Vec::new();
+        for i in 0..self.triangle_count {
+            let tri = self.triangle(i);
+            if !(finite(tri.a) && finite(tri.b) && finite(tri.c)) {
+                cleanup.non_finite_triangles += 1;
+                continue;
+            }
+            let welded = Triangle {
+                a: weld(tri.a),
+                b: weld(tri.b),
+                c: weld(tri.c),
+            };
+            cleanup.welded_corners += [(tri.a, welded.a), (tri.b, welded.b), (tri.c, welded.c)]
+                .iter()
+                .filter(|(v, welded)| v != welded)
+                .count();
+            if welded.area() == 0.0 {
+                cleanup.degenerate_triangles += 1;
+                continue;
+            }
+            triangles.push(welded);
+            if let Some(color) = self.vertex_colors.get(i) {
+                colors.push(*color);
+            }
+        }
+
+        let mesh = Mesh::from_triangles(&triangles);
+        let mesh = if self.has_vertex_colors() {
+            mesh.with_vertex_colors(colors)
+        } else {
+            mesh
+        };
+        return (mesh, cleanup);
+    }
+
+    /// Smoothed copy of the mesh after `levels` steps of Loop subdivision,
+    /// each of which splits every triangle into four. Triangles are connected
+    /// where their corners are at exactly the same position, so meshes should
+    /// be welded first. Boundary edges stay on the boundary curve, and vertex
+    /// colors are interpolated linearly.
+    pub fn subdivided(&self, levels: u32) -> Self {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = mesh.subdivided_once();
+        }
+        return mesh;
+    }
+
+    fn subdivided_once(&self) -> Self {
+        // Shared vertices and the faces around each edge
+        let mut indices: HashMap<[u64; 3], usize, BuildHasherDefault<CellHasher>> =
+            HashMap::default();
+        let mut vertices = Vec::new();
+        let mut index = |v: Vector| {
+            *indices
+                .entry([v.x.to_bits(), v.y.to_bits(), v.z.to_bits()])
+                .or_insert_with(|| {
+                    vertices.push(v);
+                    vertices.len() - 1
+                })
+        };
+        let faces: Vec<[usize; 3]> = (0..self.triangle_count)
+            .map(|i| {
+                let tri = self.triangle(i);
+                return [index(tri.a), index(tri.b), index(tri.c)];
+            })
+            .collect();
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+        // Opposite corners of the faces next to each edge
+        let mut edges: HashMap<(usize, usize), Vec<usize>, BuildHasherDefault<CellHasher>> =
+            HashMap::default();
+        for &[a, b, c] in &faces {
+            for (from, to, opposite) in [(a, b, c), (b, c, a), (c, a, b)] {
+                edges.entry(edge(from, to)).or_default().push(opposite);
+            }
+        }
+
+        let edge_point = |a: usize, b: usize| -> Vector {
+            let (va, vb) = (vertices[a], vertices[b]);
+            return match edges[&edge(a, b)].as_slice() {
+                &[c, d] => (va + vb) * (3.0 / 8.0) + (vertices[c] + vertices[d]) * (1.0 / 8.0),
+                // Boundary or non-manifold edges are split in the middle
+                _ => (va + vb) * 0.5,
+            };
+        };
+        let mut neighbors = vec![Vec::new(); vertices.len()];
+        let mut boundary_neighbors = vec![Vec::new(); vertices.len()];
+        for (&(a, b), opposite) in &edges {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            if opposite.len() == 1 {
+                boundary_neighbors[a].push(b);
+                boundary_neighbors[b].push(a);
+            }
+        }
+        let vertex_point = |v: usize| -> Vector {
+            let position = vertices[v];
+            return match boundary_neighbors[v].as_slice() {
+                [] => {
+                    let n = neighbors[v].len() as f64;
+                    let beta = if n == 3.0 {
+                        3.0 / 16.0
+                    } else {
+                        3.0 / (8.0 * n)
+                    };
+                    let sum = neighbors[v]
+                        .iter()
+                        .fold(Vector::zero(), |sum, &u| sum + vertices[u]);
+                    position * (1.0 - n * beta) + sum * beta
+                }
+                &[a, b] => position * 0.75 + (vertices[a] + vertices[b]) * 0.125,
+                // Corners where several boundaries meet stay in place
+                _ => position,
+            };
+        };
+        let moved: Vec<Vector> = (0..vertices.len()).map(vertex_point).collect();
+
+        let mut triangles = Vec::with_capacity(4 * faces.len());
+        let mut colors = Vec::new();
+        for (i, &[a, b, c]) in faces.iter().enumerate() {
+            let (ab, bc, ca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+            let (a, b, c) = (moved[a], moved[b], moved[c]);
+            for (a, b, c) in [(a, ab, ca), (ab, b, bc), (ca, bc, c), (ab, bc, ca)] {
+                triangles.push(Triangle { a, b, c });
+            }
+            if let Some(&[color_a, color_b, color_c]) = self.vertex_colors.get(i) {
+                let (color_ab, color_bc, color_ca) = (
+                    (color_a + color_b) * 0.5,
+                    (color_b + color_c) * 0.5,
+                    (color_c + color_a) * 0.5,
+                );
+                colors.push([color_a, color_ab, color_ca]);
+                colors.push([color_ab, color_b, color_bc]);
+                colors.push([color_ca, color_bc, color_c]);
+                colors.push([color_ab, color_bc, color_ca]);
+            }
+        }
+
+        let mesh = Mesh::from_triangles(&triangles);
+        return if colors.is_empty() {
+            mesh
+        } else {
+            mesh.with_vertex_colors(colors)
+        };
+    }
+
+    pub fn has_vertex_colors(&self) -> bool {
+        return !self.vertex_colors.is_empty();
+    }
+
+    /// Vertex color of triangle `i` interpolated at `point`, which lies on it.
+    fn vertex_color(&self, i: usize, point: Vector) -> Option<Vector> {
+        let [color_a, color_b, color_c] = self.vertex_colors.get(i)?;
+        let tri = self.triangle(i);
+        let normal = (tri.b - tri.a).cross(&(tri.c - tri.a));
+        let area = normal.dot(&normal);
+        if area == 0.0 {
+            return Some(*color_a);
+        }
+        // Barycentric coordinates from the areas of the sub-triangles
+        let u = (tri.c - tri.b).cross(&(point - tri.b)).dot(&normal) / area;
+        let v = (tri.a - tri.c).cross(&(point - tri.c)).dot(&normal) / area;
+        return Some(*color_a * u + *color_b * v + *color_c * (1.0 - u - v));
+    }
+
+    /// Total surface area
+    pub fn area(&self) -> f64 {
+        return self.area_cdf.last().copied().unwrap_or(0.0);
+    }
+
+    /// Uniformly distributed point on the surface in local coordinates, and
+    /// the normal of the triangle it lies on. Without triangles, this is the
+    /// origin and a zero normal.
+    pub fn sample_point(&self, r1: f64, r2: f64, r3: f64) -> (Vector, Vector) {
+        if self.triangle_count == 0 {
+            return (Vector::zero(), Vector::zero());
+        }
+        let target = r1 * self.area();
+        let i = self
+            .area_cdf
+            .partition_point(|area| *area <= target)
+            .min(self.triangle_count - 1);
+        let tri = self.triangle(i);
+
+        let su = r2.sqrt();
+        let point = tri.a * (1.0 - su) + tri.b * (su * (1.0 - r3)) + tri.c * (su * r3);
+        return (point, (tri.b - tri.a).cross(&(tri.c - tri.a)).normalize());
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+
+    pub fn triangle(&self, i: usize) -> Triangle {
+        self.triangles.get(i)
+    }
+
+    /// Encodes the mesh for `from_bytes`: the magic bytes, the triangle count
+    /// and whether there are vertex colors, followed by the vertices and the
+    /// vertex colors of each triangle, all as little endian 64 bit values.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let colors = self.has_vertex_colors();
+        let values = 9 * self.triangle_count * if colors { 2 } else { 1 };
+        let mut bytes = Vec::with_capacity(24 + 8 * values);
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.extend_from_slice(&(self.triangle_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&(colors as u64).to_le_bytes());
+        let mut push = |vertices: [Vector; 3]| {
+            for v in vertices {
+                for value in [v.x, v.y, v.z] {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        };
+        for i in 0..self.triangle_count {
+            let tri = self.triangle(i);
+            push([tri.a, tri.b, tri.c]);
+        }
+        for colors in &self.vertex_colors {
+            push(*colors);
+        }
+        return bytes;
+    }
+
+    /// Decodes a mesh written by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let bad_data = |reason: &str| Err(Error::new(ErrorKind::InvalidData, reason.to_owned()));
+        let Some((header, data)) = data.split_first_chunk::<24>() else {
+            return bad_data("Binary mesh ended early");
+        };
+        if &header[..8] != BINARY_MAGIC {
+            return bad_data("Invalid binary mesh header");
+        }
+        let word = |i: usize| u64::from_le_bytes(header[i * 8..i * 8 + 8].try_into().unwrap());
+        let (triangle_count, colors) = (word(1) as usize, word(2) != 0);
+        let values = triangle_count
+            .checked_mul(if colors { 18 } else { 9 })
+            .filter(|values| values.checked_mul(8) == Some(data.len()));
+        if values.is_none() {
+            return bad_data("Binary mesh has the wrong size");
+        }
+
+        let mut vertices = data.chunks_exact(24).map(|v| {
+            let value = |i: usize| f64::from_le_bytes(v[i * 8..i * 8 + 8].try_into().unwrap());
+            return Vector::from(value(0), value(1), value(2));
+        });
+        let mut next = || -> [Vector; 3] {
+            return std::array::from_fn(|_| vertices.next().unwrap());
+        };
+        let triangles: Vec<Triangle> = (0..triangle_count)
+            .map(|_| {
+                let [a, b, c] = next();
+                return Triangle { a, b, c };
+            })
+            .collect();
+        let mesh = Mesh::from_triangles(&triangles);
+        return Ok(if colors {
+            mesh.with_vertex_colors((0..triangle_count).map(|_| next()).collect())
+        } else {
+            mesh
+        });
+    }
+
+    /// Intersects a ray given in the mesh's local coordinates with all
+    /// triangles and returns the closest hit.
+    pub fn intersect(&self, ray: &Ray) -> IntersectResult {
+        let sheared = ShearedRay::new(ray);
+        #[cfg(feature = "simd")]
+        let closest = self.closest_triangle_simd(&sheared);
+        #[cfg(not(feature = "simd"))]
+        let closest = self.closest_triangle(&sheared);
+
+        return match closest {
+            None => IntersectResult::NoHit,
+            Some((i, distance)) => {
+                let tri = self.triangle(i);
+                let intersection = ray.origin + ray.direction * distance;
+                IntersectResult::Hit(Hit {
+                    distance,
+                    intersection,
+                    normal: (tri.b - tri.a).cross(&(tri.c - tri.a)).normalize(),
+                    vertex_color: self.vertex_color(i, intersection),
+                })
+            }
+        };
+    }
+
+    #[cfg_attr(feature = "simd", allow(dead_code))]
+    pub(crate) fn closest_triangle(&self, ray: &ShearedRay) -> Option<(usize, f64)> {
+        let mut closest: Option<(usize, f64)> = None;
+        for i in 0..self.triangle_count {
+            let tri = self.triangle(i);
+            if let Some(distance) = ray.intersect(&tri) {
+                if closest.is_none_or(|(_, d)| distance < d) {
+                    closest = Some((i, distance));
+                }
+            }
+        }
+        return closest;
+    }
+
+    #[cfg(feature = "simd")]
+    pub(crate) fn closest_triangle_simd(&self, ray: &ShearedRay) -> Option<(usize, f64)> {
+        use wide::{f64x4, CmpGe, CmpGt, CmpLe, CmpNe};
+
+        let load = |v: &Vec<f64>, i: usize| f64x4::new(v[i..i + BATCH_SIZE].try_into().unwrap());
+        let tris = &self.triangles;
+        let [kx, ky, kz] = ray.axes;
+        let a = [&tris.ax, &tris.ay, &tris.az];
+        let b = [&tris.bx, &tris.by, &tris.bz];
+        let c = [&tris.cx, &tris.cy, &tris.cz];
+        let [ox, oy, oz] = ray.origin.map(f64x4::splat);
+        let [sx, sy, sz] = ray.shear.map(f64x4::splat);
+        let zero = f64x4::splat(0.0);
+        let min_distance = f64x4::splat(ray.min_distance);
+
+        // Vertex relative to the ray origin, in the sheared space
+        let project = |v: [&Vec<f64>; 3], i: usize| {
+            let z = load(v[kz], i) - oz;
+            return (
+                load(v[kx], i) - ox - sx * z,
+                load(v[ky], i) - oy - sy * z,
+                sz * z,
+            );
+        };
+
+        let mut closest: Option<(usize, f64)> = None;
+        for i in (0..tris.ax.len()).step_by(BATCH_SIZE) {
+            let (ax, ay, az) = project(a, i);
+            let (bx, by, bz) = project(b, i);
+            let (cx, cy, cz) = project(c, i);
+
+            let u = cx * by - cy * bx;
+            let v = ax * cy - ay * cx;
+            let w = bx * ay - by * ax;
+            let inside_front = u.cmp_ge(zero) & v.cmp_ge(zero) & w.cmp_ge(zero);
+            let inside = if USE_CULLING {
+                inside_front
+            } else {
+                inside_front | (u.cmp_le(zero) & v.cmp_le(zero) & w.cmp_le(zero))
+            };
+
+            let determinant = u + v + w;
+            let distance = (u * az + v * bz + w * cz) / determinant;
+            let valid = inside & determinant.cmp_ne(zero) & distance.cmp_gt(min_distance);
+            if valid.none() {
+                continue;
+            }
+
+            let mask = valid.move_mask();
+            let distances = distance.to_array();
+            for (lane, distance) in distances.iter().enumerate() {
+                if mask & (1 << lane) != 0 && closest.is_none_or(|(_, d)| *distance < d) {
+                    closest = Some((i + lane, *distance));
+                }
+            }
+        }
+        return closest;
+    }
+}
+
+/// Ray prepared for watertight ray/triangle intersection (Woop, Benthin and
+/// Wald 2013). Vertices are translated to the ray origin and sheared so that
+/// the ray points along +z, and the edge tests become 2D. Neighbouring
+/// triangles compute the exact same value for a shared edge, so rays can't
+/// slip through the gap between them.
+pub(crate) struct ShearedRay {
+    /// Permutation of the axes that makes z the largest direction component
+    axes: [usize; 3],
+    /// Ray origin, in permuted axis order
+    origin: [f64; 3],
+    shear: [f64; 3],
+    min_distance: f64,
+}
+
+impl ShearedRay {
+    pub(crate) fn new(ray: &Ray) -> Self {
+        let d = ray.direction;
+        let kz = if d.x.abs() > d.y.abs() {
+            if d.x.abs() > d.z.abs() {
+                0
+            } else {
+                2
+            }
+        } else if d.y.abs() > d.z.abs() {
+            1
+        } else {
+            2
+        };
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        // Keep the winding, and with it the sign of the determinant
+        if d.axis(kz) < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        let dz = d.axis(kz);
+        return ShearedRay {
+            axes: [kx, ky, kz],
+            origin: [
+                ray.origin.axis(kx),
+                ray.origin.axis(ky),
+                ray.origin.axis(kz),
+            ],
+            shear: [d.axis(kx) / dz, d.axis(ky) / dz, 1.0 / dz],
+            min_distance: min_hit_distance(ray.origin.max_abs()),
+        };
+    }
+
+    fn project(&self, v: Vector) -> (f64, f64, f64) {
+        let [kx, ky, kz] = self.axes;
+        let z = v.axis(kz) - self.origin[2];
+        return (
+            v.axis(kx) - self.origin[0] - self.shear[0] * z,
+            v.axis(ky) - self.origin[1] - self.shear[1] * z,
+            self.shear[2] * z,
+        );
+    }
+
+    /// Returns the hit distance.
+    fn intersect(&self, tri: &Triangle) -> Option<f64> {
+        let (ax, ay, az) = self.project(tri.a);
+        let (bx, by, bz) = self.project(tri.b);
+        let (cx, cy, cz) = self.project(tri.c);
+
+        // Scaled barycentric coordinates, all with the same sign inside
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        if USE_CULLING {
+            if u < 0.0 || v < 0.0 || w < 0.0 {
+                return None;
+            }
+        } else if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+
+        // Zero for a ray in the triangle's plane or a degenerate triangle
+        let determinant = u + v + w;
+        if determinant == 0.0 {
+            return None;
+        }
+
+        let distance = (u * az + v * bz + w * cz) / determinant;
+        if distance <= self.min_distance {
+            return None;
+        }
+        return Some(distance);
+    }
+}
@@ -0,0 +1,227 @@
+use std::io::{Error, ErrorKind};
+
+use tracing::debug;
+
+use crate::{
+    load_off::parse_off_bytes,
+    load_ply::parse_ply,
+    load_stl::parse_stl,
+    mesh::{Mesh, MeshCleanup, UpAxis},
+    with_path, Vector,
+};
+
+/// Length unit of the coordinates in a mesh file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Meters,
+    Centimeters,
+    Millimeters,
+    Inches,
+    Feet,
+}
+
+impl Unit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "m" => Some(Unit::Meters),
+            "cm" => Some(Unit::Centimeters),
+            "mm" => Some(Unit::Millimeters),
+            "in" => Some(Unit::Inches),
+            "ft" => Some(Unit::Feet),
+            _ => None,
+        };
+    }
+
+    pub fn in_meters(&self) -> f64 {
+        return match self {
+            Unit::Meters => 1.0,
+            Unit::Centimeters => 0.01,
+            Unit::Millimeters => 0.001,
+            Unit::Inches => 0.0254,
+            Unit::Feet => 0.3048,
+        };
+    }
+}
+
+/// Mesh file together with the conversions that bring its contents into
+/// the scene: first the up axis, then the unit and scale, then the rotation.
+/// Before converting, the mesh is cleaned up by `Mesh::sanitized` and
+/// optionally smoothed by `Mesh::subdivided`.
+#[derive(Clone, Debug)]
+pub struct MeshFile {
+    /// OFF, STL or PLY file, chosen by extension
+    pub path: String,
+    pub up: UpAxis,
+    pub unit: Unit,
+    /// Per-axis scale, on top of the unit conversion
+    pub scale: Vector,
+    /// Rotation in degrees around the x, then y, then z axis
+    pub rotation: Vector,
+    /// Vertices closer than this fraction of the largest coordinate are
+    /// welded, 0 only removes broken triangles
+    pub weld_tolerance: f64,
+    /// Steps of Loop subdivision, each splitting every triangle into four
+    pub subdivisions: u32,
+    /// Keep the parsed mesh in a `.meshbin` file next to the source and load
+    /// that instead while the source is unchanged
+    pub cache: bool,
+}
+
+impl MeshFile {
+    /// Y-up file in meters, loaded unchanged
+    pub fn new(path: &str) -> Self {
+        return MeshFile {
+            path: path.to_owned(),
+            up: UpAxis::Y,
+            unit: Unit::Meters,
+            scale: Vector::uniform(1.0),
+            rotation: Vector::zero(),
+            weld_tolerance: 1e-6,
+            subdivisions: 0,
+            cache: false,
+        };
+    }
+
+    pub fn load(&self) -> Result<Mesh, Error> {
+        let (mesh, cleanup) = self.load_with_cleanup()?;
+        debug!(
+            path = %self.path,
+            triangles = mesh.triangle_count(),
+            welded_corners = cleanup.welded_corners,
+            degenerate_triangles = cleanup.degenerate_triangles,
+            non_finite_triangles = cleanup.non_finite_triangles,
+            "Loaded mesh"
+        );
+        return Ok(mesh);
+    }
+
+    /// Like `load`, but also returns what the cleanup changed, for logging
+    pub fn load_with_cleanup(&self) -> Result<(Mesh, MeshCleanup), Error> {
+        let data = with_path(std::fs::read(&self.path), &self.path)?;
+        if !self.cache {
+            return self.parse_with_cleanup(&data);
+        }
+
+        // The cache holds the cleaned up and subdivided mesh
+        let key = [
+            source_hash(&data),
+            self.weld_tolerance.to_bits(),
+            self.subdivisions as u64,
+        ];
+        let cache_path = self.cache_path();
+        let cached = std::fs::read(&cache_path).ok().and_then(|cache| {
+            let (header, mesh) = cache.split_first_chunk::<48>()?;
+            let word = |i: usize| u64::from_le_bytes(header[i * 8..i * 8 + 8].try_into().unwrap());
+            if [word(0), word(1), word(2)] != key {
+                return None;
+            }
+            let cleanup = MeshCleanup {
+                welded_corners: word(3) as usize,
+                degenerate_triangles: word(4) as usize,
+                non_finite_triangles: word(5) as usize,
+            };
+            return Some((Mesh::from_bytes(mesh).ok()?, cleanup));
+        });
+        let (mesh, cleanup) = match cached {
+            Some(cached) => {
+                debug!(path = %cache_path, "Using cached mesh");
+                cached
+            }
+            None => {
+                let (mesh, cleanup) = self.parse_cleaned_up(&data)?;
+                let header = [
+                    key[0],
+                    key[1],
+                    key[2],
+                    cleanup.welded_corners as u64,
+                    cleanup.degenerate_triangles as u64,
+                    cleanup.non_finite_triangles as u64,
+                ];
+                let mut cache: Vec<u8> = header.iter().flat_map(|w| w.to_le_bytes()).collect();
+                cache.extend_from_slice(&mesh.to_bytes());
+                // Without a cache, the next load just parses the source again
+                let _ = std::fs::write(&cache_path, cache);
+                (mesh, cleanup)
+            }
+        };
+        return Ok((self.transformed(mesh), cleanup));
+    }
+
+    /// Where `load` keeps the parsed mesh if `cache` is set: the source path
+    /// with `.meshbin` appended
+    pub fn cache_path(&self) -> String {
+        return format!("{}.meshbin", self.path);
+    }
+
+    /// Like `load`, but with the file contents in `data`. `path` only selects
+    /// the format.
+    pub fn parse(&self, data: &[u8]) -> Result<Mesh, Error> {
+        return self.parse_with_cleanup(data).map(|(mesh, _)| mesh);
+    }
+
+    /// Like `parse`, but also returns what the cleanup changed
+    pub fn parse_with_cleanup(&self, data: &[u8]) -> Result<(Mesh, MeshCleanup), Error> {
+        let (mesh, cleanup) = self.parse_cleaned_up(data)?;
+        return Ok((self.transformed(mesh), cleanup));
+    }
+
+    fn parse_cleaned_up(&self, data: &[u8]) -> Result<(Mesh, MeshCleanup), Error> {
+        let extension = std::path::Path::new(&self.path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let mesh = match extension.as_deref() {
+            Some("off") => with_path(parse_off_bytes(data, 1.0), &self.path)?,
+            Some("stl") => with_path(parse_stl(data, 1.0, UpAxis::Y), &self.path)?,
+            Some("ply") => with_path(parse_ply(data, 1.0, UpAxis::Y), &self.path)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unsupported mesh file: {}", self.path),
+                ))
+            }
+        };
+        let size = (0..mesh.triangle_count())
+            .flat_map(|i| {
+                let tri = mesh.triangle(i);
+                return [tri.a, tri.b, tri.c];
+            })
+            .map(|v| v.max_abs())
+            .filter(|size| size.is_finite())
+            .fold(0.0, f64::max);
+        let (mesh, cleanup) = mesh.sanitized(self.weld_tolerance * size);
+        return Ok((mesh.subdivided(self.subdivisions), cleanup));
+    }
+
+    /// Applies the conversions to `mesh`, skipping the copy for files that
+    /// are loaded unchanged
+    fn transformed(&self, mesh: Mesh) -> Mesh {
+        let unchanged = self.up == UpAxis::Y
+            && self.unit == Unit::Meters
+            && self.scale == Vector::uniform(1.0)
+            && self.rotation == Vector::zero();
+        if unchanged {
+            return mesh;
+        }
+        return mesh.transformed(|v| self.transform(v));
+    }
+
+    fn transform(&self, v: Vector) -> Vector {
+        let v = self.up.to_y_up(v) * self.scale * self.unit.in_meters();
+        return v.rotate_xyz(self.rotation);
+    }
+}
+
+/// FNV-1a over 8 byte words, which is enough to notice that a source file
+/// changed
+fn source_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut words = data.chunks_exact(8);
+    for word in &mut words {
+        hash = (hash ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(0x100_0000_01b3);
+    }
+    for &byte in words.remainder() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+    }
+    return hash ^ data.len() as u64;
+}
@@ -0,0 +1,274 @@
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::TcpStream,
+    ops::Range,
+    sync::{Condvar, Mutex},
+};
+
+use tracing::{debug, info, warn};
+
+use crate::{map_pixels, render_pixel, tenths_passed, RenderConfig, SceneData, Sensor, Vector};
+
+// Protocol, one coordinator connection at a time:
+//   -> ARGS <n>, followed by the n command line arguments, one per line
+//   <- OK <width> <height>, or ERR <message>
+//   -> TILE <first pixel> <pixel count>, any number of times
+//   <- red, green, blue and alpha of each pixel as little endian f64
+//   -> END
+
+/// Pixels per tile sent to a worker
+const TILE_SIZE: usize = 4096;
+
+/// Renders tiles for the coordinator connected through `stream` until it
+/// sends END. The scene is selected from `scenes` by the received arguments.
+pub fn serve(stream: TcpStream, scenes: &[SceneData]) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let count = match read_command(&mut reader)?.as_slice() {
+        [command, count] if command == "ARGS" => parse_number(count)?,
+        _ => return Err(protocol_error("expected ARGS")),
+    };
+    let args = (0..count)
+        .map(|_| read_line(&mut reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    let job = RenderConfig::with_scene(args, scenes)
+        .ok_or_else(|| "Invalid arguments or unknown scene".to_owned())
+        .and_then(|(config, mut scene)| {
+            scene.apply_render_config(&config)?;
+            return Ok((config, scene));
+        });
+    let (config, scene) = match job {
+        Ok(job) => job,
+        Err(message) => {
+            writeln!(writer, "ERR {}", message)?;
+            return Err(protocol_error(&message));
+        }
+    };
+    let pixel_count = config.resolution_x() * config.resolution_y;
+    debug!(
+        scene = %config.scene_id,
+        width = config.resolution_x(),
+        height = config.resolution_y,
+        "Accepted render job"
+    );
+    writeln!(
+        writer,
+        "OK {} {}",
+        config.resolution_x(),
+        config.resolution_y
+    )?;
+
+    let scene = scene.with_photon_map(&config);
+    let sensor = Sensor::new(&scene.camera, &config);
+    loop {
+        let tile = match read_command(&mut reader)?.as_slice() {
+            [command, first, count] if command == "TILE" => {
+                let first = parse_number(first)?;
+                let end = first
+                    .checked_add(parse_number(count)?)
+                    .ok_or_else(|| protocol_error("tile outside of the image"))?;
+                first..end
+            }
+            [command] if command == "END" => return Ok(()),
+            _ => return Err(protocol_error("expected TILE or END")),
+        };
+        if tile.end > pixel_count {
+            return Err(protocol_error("tile outside of the image"));
+        }
+        debug!(first = tile.start, count = tile.len(), "Rendering tile");
+        let pixels = map_pixels(tile, |pixel_index| {
+            render_pixel(&scene, &config, &sensor, pixel_index)
+        });
+        let mut bytes = Vec::with_capacity(pixels.len() * 32);
+        for (color, alpha) in pixels {
+            for value in [color.x, color.y, color.z, alpha] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        writer.write_all(&bytes)?;
+    }
+}
+
+/// Renders the image described by the command line `args` on the workers at
+/// `addresses`, which each render a share of the tiles. Returns the pixels
+/// and alpha like `render_with_alpha`.
+pub fn render_distributed(
+    args: &[String],
+    addresses: &[String],
+    render_config: &RenderConfig,
+) -> Result<(Vec<Vector>, Vec<f64>), Error> {
+    let pixel_count = render_config.resolution_x() * render_config.resolution_y;
+    let tiles = (0..pixel_count)
+        .step_by(TILE_SIZE)
+        .map(|first| first..(first + TILE_SIZE).min(pixel_count))
+        .rev()
+        .collect::<Vec<_>>();
+    let tile_count = tiles.len();
+    let queue = Mutex::new(TileQueue {
+        tiles,
+        in_flight: 0,
+    });
+    // Signalled whenever a tile is finished or returned by a failed worker
+    let tile_done = Condvar::new();
+    let finished = Mutex::new(Vec::new());
+    let errors = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for address in addresses {
+            let (queue, tile_done, finished, errors) = (&queue, &tile_done, &finished, &errors);
+            scope.spawn(move || {
+                let next_tile = || {
+                    let mut queue = queue.lock().unwrap();
+                    loop {
+                        if let Some(tile) = queue.tiles.pop() {
+                            queue.in_flight += 1;
+                            return Some(tile);
+                        }
+                        if queue.in_flight == 0 {
+                            return None;
+                        }
+                        // Stay connected in case another worker fails and returns its tile
+                        queue = tile_done.wait(queue).unwrap();
+                    }
+                };
+                let finish = |result| {
+                    let mut finished = finished.lock().unwrap();
+                    finished.push(result);
+                    if !render_config.quiet && tenths_passed(finished.len(), tile_count) {
+                        info!(
+                            tiles = finished.len(),
+                            total = tile_count,
+                            "Rendering ... {} / {} tiles",
+                            finished.len(),
+                            tile_count
+                        );
+                    }
+                    queue.lock().unwrap().in_flight -= 1;
+                    tile_done.notify_all();
+                };
+                match work(address, args, render_config, next_tile, finish) {
+                    Ok(()) => (),
+                    // Leave the tile for the remaining workers
+                    Err((tile, e)) => {
+                        warn!(worker = %address, "Worker failed: {}", e);
+                        if let Some(tile) = tile {
+                            let mut queue = queue.lock().unwrap();
+                            queue.tiles.push(tile);
+                            queue.in_flight -= 1;
+                        }
+                        tile_done.notify_all();
+                        errors.lock().unwrap().push(format!("{}: {}", address, e));
+                    }
+                }
+            });
+        }
+    });
+
+    let finished = finished.into_inner().unwrap();
+    if finished.len() < tile_count {
+        let errors = errors.into_inner().unwrap();
+        return Err(Error::other(format!(
+            "{} of {} tiles were not rendered ({})",
+            tile_count - finished.len(),
+            tile_count,
+            errors.join(", ")
+        )));
+    }
+    let mut pixels = vec![Vector::zero(); pixel_count];
+    let mut alpha = vec![0.0; pixel_count];
+    for (tile, values) in finished {
+        for (i, values) in tile.zip(values.chunks_exact(4)) {
+            pixels[i] = Vector::from(values[0], values[1], values[2]);
+            alpha[i] = values[3];
+        }
+    }
+    return Ok((pixels, alpha));
+}
+
+type TileResult = (Range<usize>, Vec<f64>);
+
+/// Tiles of a distributed render that no worker has finished yet
+struct TileQueue {
+    /// Waiting to be sent to a worker
+    tiles: Vec<Range<usize>>,
+    /// Sent to a worker that hasn't returned them yet
+    in_flight: usize,
+}
+
+/// Sends tiles from `next_tile` to one worker until there are none left and
+/// passes the results to `finish`. On failure, returns the unfinished tile.
+fn work(
+    address: &str,
+    args: &[String],
+    render_config: &RenderConfig,
+    next_tile: impl Fn() -> Option<Range<usize>>,
+    finish: impl Fn(TileResult),
+) -> Result<(), (Option<Range<usize>>, Error)> {
+    let connect = || -> Result<_, Error> {
+        let stream = TcpStream::connect(address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        writeln!(writer, "ARGS {}", args.len())?;
+        for arg in args {
+            if arg.contains('\n') {
+                return Err(Error::new(ErrorKind::InvalidInput, "argument with newline"));
+            }
+            writeln!(writer, "{}", arg)?;
+        }
+        let expected = [
+            "OK".to_owned(),
+            render_config.resolution_x().to_string(),
+            render_config.resolution_y.to_string(),
+        ];
+        let reply = read_command(&mut reader)?;
+        if reply != expected {
+            return Err(protocol_error(&reply.join(" ")));
+        }
+        return Ok((reader, writer));
+    };
+    let (mut reader, mut writer) = connect().map_err(|e| (None, e))?;
+
+    while let Some(tile) = next_tile() {
+        let mut render = || -> Result<Vec<f64>, Error> {
+            writeln!(writer, "TILE {} {}", tile.start, tile.len())?;
+            let mut bytes = vec![0; tile.len() * 32];
+            reader.read_exact(&mut bytes)?;
+            return Ok(bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect());
+        };
+        match render() {
+            Ok(values) => finish((tile, values)),
+            Err(e) => return Err((Some(tile), e)),
+        }
+    }
+    writeln!(writer, "END").map_err(|e| (None, e))?;
+    return Ok(());
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    return Ok(line.trim_end_matches(['\r', '\n']).to_owned());
+}
+
+fn read_command(reader: &mut impl BufRead) -> Result<Vec<String>, Error> {
+    return Ok(read_line(reader)?
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect());
+}
+
+fn parse_number(s: &str) -> Result<usize, Error> {
+    return s
+        .parse()
+        .map_err(|_| protocol_error(&format!("invalid number {}", s)));
+}
+
+fn protocol_error(message: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, message.to_owned());
+}
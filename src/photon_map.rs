@@ -0,0 +1,314 @@
+use std::{collections::HashMap, f64::consts::PI, hash::BuildHasherDefault};
+
+use crate::{
+    beer_lambert, hash::CellHasher, intersect_scene, offset_origin, rand01, refract, Ray,
+    ReflectType, SceneData, SceneIntersectResult, SceneObject, SceneObjectData, Vector, Visibility,
+    MAX_DEPTH,
+};
+
+/// Light that arrived at a diffuse surface after specular bounces only
+#[derive(Clone, Copy, Debug)]
+struct Photon {
+    position: Vector,
+    direction: Vector,
+    power: Vector,
+}
+
+/// Caustic photons traced from the emissive objects, for estimating the
+/// light that paths can't find through mirrors and glass.
+#[derive(Debug)]
+pub struct PhotonMap {
+    /// Photons by grid cell, with cells twice as large as the gather radius,
+    /// so that each gather looks into at most 8 cells
+    cells: HashMap<(i64, i64, i64), Vec<Photon>, BuildHasherDefault<CellHasher>>,
+    radius: f64,
+    len: usize,
+    /// Corners of the box around all photons
+    bounds: (Vector, Vector),
+}
+
+impl PhotonMap {
+    /// Emits `count` photons from the emissive objects of `scene` towards its
+    /// mirrors and glass, and keeps those that reach a diffuse surface.
+    /// Photons within `radius` of a point are averaged for its estimate.
+    pub fn build(scene: &SceneData, count: usize, radius: f64) -> Self {
+        let mut map = PhotonMap {
+            cells: HashMap::default(),
+            radius,
+            len: 0,
+            bounds: (
+                Vector::uniform(f64::INFINITY),
+                Vector::uniform(-f64::INFINITY),
+            ),
+        };
+        let visible = |object: &SceneObjectData| object.visibility != Visibility::Hidden;
+        let lights = scene.objects.iter().enumerate().filter(|(_, light)| {
+            visible(light)
+                && luminance(light.material.emmission) > 0.0
+                && !matches!(light.type_, SceneObject::Plane { .. })
+        });
+        let targets = scene.objects.iter().enumerate().filter(|(_, target)| {
+            visible(target)
+                && matches!(
+                    target.material.reflect_type,
+                    ReflectType::Specular | ReflectType::Refract { .. }
+                )
+        });
+        // Each photon path is estimated by the pair of its light and the
+        // first object it hits. Pairs are chosen by the light's power, so
+        // lights without area, like empty meshes, are left out.
+        let pairs: Vec<(usize, usize, f64)> = lights
+            .flat_map(|(light_id, light)| {
+                let power = luminance(light.material.emmission) * surface_area(&light.type_);
+                targets
+                    .clone()
+                    .filter(move |(target_id, _)| *target_id != light_id)
+                    .map(move |(target_id, _)| (light_id, target_id, power))
+            })
+            .filter(|(_, _, power)| *power > 0.0)
+            .collect();
+        let total: f64 = pairs.iter().map(|(_, _, power)| power).sum();
+        if count == 0 || total <= 0.0 {
+            return map;
+        }
+
+        for _ in 0..count {
+            let mut choice = rand01() * total;
+            let &(light_id, target_id, power) = pairs
+                .iter()
+                .find(|(_, _, power)| {
+                    choice -= power;
+                    choice < 0.0
+                })
+                .unwrap_or(pairs.last().unwrap());
+            let light = &scene.objects[light_id];
+            let target = scene.objects[target_id].bounding_sphere();
+            let (point, normal, area) = sample_surface(light, target);
+            let Some((direction, weight)) = sample_direction(point, normal, target) else {
+                continue;
+            };
+            let ray = Ray {
+                origin: offset_origin(point, normal, direction),
+                direction,
+            };
+            let probability = power / total;
+            let photon_power =
+                light.material.emmission * (area * weight / (probability * count as f64));
+            map.trace(scene, ray, target_id, photon_power);
+        }
+        return map;
+    }
+
+    /// Follows a photon that must first hit `target_id` through specular
+    /// bounces and stores it where it lands on a diffuse surface.
+    fn trace(&mut self, scene: &SceneData, mut ray: Ray, target_id: usize, mut power: Vector) {
+        let max_depth = scene.render_settings.max_depth.unwrap_or(MAX_DEPTH);
+        for depth in 0..max_depth {
+            let (object_id, hit) = match intersect_scene(&ray, &scene.objects, false) {
+                SceneIntersectResult::Hit { object_id, hit }
+                    if depth > 0 || object_id == target_id =>
+                {
+                    (object_id, hit)
+                }
+                _ => return,
+            };
+            let material = &scene.objects[object_id].material;
+            let reflected = ray.direction - hit.normal * 2.0 * hit.normal.dot(&ray.direction);
+            let direction = match material.reflect_type {
+                ReflectType::Diffuse => {
+                    self.store(Photon {
+                        position: hit.intersection,
+                        direction: ray.direction,
+                        power,
+                    });
+                    return;
+                }
+                ReflectType::Specular => reflected,
+                ReflectType::Refract { ior, absorption } => {
+                    if hit.normal.dot(&ray.direction) > 0.0 {
+                        power = power * beer_lambert(absorption, hit.distance);
+                    }
+                    match refract(ray.direction, hit.normal, ior) {
+                        Some((refracted, re)) if rand01() >= re => refracted,
+                        _ => reflected,
+                    }
+                }
+            };
+            power = power * material.color;
+            if power.max_abs() <= 0.0 {
+                return;
+            }
+            ray = hit.spawn_ray(direction);
+        }
+    }
+
+    fn store(&mut self, photon: Photon) {
+        let (min, max) = self.bounds;
+        let p = photon.position;
+        self.bounds = (
+            Vector::from(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            Vector::from(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        );
+        self.cells.entry(self.cell(p)).or_default().push(photon);
+        self.len += 1;
+    }
+
+    fn cell(&self, point: Vector) -> (i64, i64, i64) {
+        let index = |x: f64| (x / (2.0 * self.radius)).floor() as i64;
+        return (index(point.x), index(point.y), index(point.z));
+    }
+
+    /// Number of stored photons
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// Caustic light reflected by a white diffuse surface at `point` with the
+    /// normal `normal` facing the viewer.
+    pub fn radiance(&self, point: Vector, normal: Vector) -> Vector {
+        let (min, max) = self.bounds;
+        let r = self.radius;
+        let outside = |p: f64, min: f64, max: f64| p < min - r || p > max + r;
+        if outside(point.x, min.x, max.x)
+            || outside(point.y, min.y, max.y)
+            || outside(point.z, min.z, max.z)
+        {
+            return Vector::zero();
+        }
+        let (x0, y0, z0) = self.cell(point - Vector::uniform(r));
+        let (x1, y1, z1) = self.cell(point + Vector::uniform(r));
+        let mut power = Vector::zero();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                for z in z0..=z1 {
+                    let Some(photons) = self.cells.get(&(x, y, z)) else {
+                        continue;
+                    };
+                    for photon in photons {
+                        if photon.direction.dot(&normal) < 0.0
+                            && (photon.position - point).magnitude() < r
+                        {
+                            power = power + photon.power;
+                        }
+                    }
+                }
+            }
+        }
+        // Irradiance over the gather disc, reflected with a BRDF of 1 / pi
+        return power / (PI * r * r * PI);
+    }
+}
+
+/// Area that emits light, with both sides of flat emitters
+fn surface_area(object: &SceneObject) -> f64 {
+    return match object {
+        SceneObject::Sphere { radius } => 4.0 * PI * radius * radius,
+        SceneObject::Disc { radius, .. } => 2.0 * PI * radius * radius,
+        SceneObject::Mesh(mesh) => 2.0 * mesh.area(),
+        SceneObject::Plane { .. } => f64::INFINITY,
+    };
+}
+
+/// Uniformly distributed point on `light` and the normal of the side that
+/// emits, together with the area the point was chosen from. On spheres, this
+/// is only the cap that can see `target`.
+fn sample_surface(light: &SceneObjectData, target: Option<(Vector, f64)>) -> (Vector, Vector, f64) {
+    let flip = |normal: Vector| {
+        if rand01() < 0.5 {
+            normal
+        } else {
+            normal * -1.0
+        }
+    };
+    return match &light.type_ {
+        SceneObject::Sphere { radius } => {
+            let cap = target.and_then(|(center, target_radius)| {
+                let offset = center - light.position;
+                let distance = offset.magnitude();
+                // The target rises above the tangent plane only within the cap
+                (distance > radius + target_radius)
+                    .then(|| (offset / distance, (radius - target_radius) / distance))
+            });
+            let (axis, min_cos) = cap.unwrap_or((Vector::from(0.0, 1.0, 0.0), -1.0));
+            let normal = sample_cone(axis, min_cos);
+            let area = 2.0 * PI * radius * radius * (1.0 - min_cos);
+            (light.position + normal * *radius, normal, area)
+        }
+        SceneObject::Disc { normal, radius } => {
+            let (u, v) = basis(*normal);
+            let r = radius * rand01().sqrt();
+            let phi = 2.0 * PI * rand01();
+            let point = light.position + u * (r * phi.cos()) + v * (r * phi.sin());
+            (point, flip(*normal), surface_area(&light.type_))
+        }
+        SceneObject::Mesh(mesh) => {
+            let (point, normal) = mesh.sample_point(rand01(), rand01(), rand01());
+            let area = surface_area(&light.type_);
+            (light.position + point, flip(normal), area)
+        }
+        SceneObject::Plane { .. } => unreachable!("planes don't emit photons"),
+    };
+}
+
+/// Direction from `point` towards the bounding sphere `target`, or
+/// cosine-weighted around `normal` for targets without one. Returns it with
+/// its cosine to `normal` divided by its probability density, or None if it
+/// points into the surface.
+fn sample_direction(
+    point: Vector,
+    normal: Vector,
+    target: Option<(Vector, f64)>,
+) -> Option<(Vector, f64)> {
+    let Some((center, radius)) = target else {
+        // Cosine-weighted like diffuse bounces, so the weight is constant
+        let (u, v) = basis(normal);
+        let r2: f64 = rand01();
+        let phi = 2.0 * PI * rand01();
+        let direction =
+            u * (phi.cos() * r2.sqrt()) + v * (phi.sin() * r2.sqrt()) + normal * (1.0 - r2).sqrt();
+        return Some((direction, PI));
+    };
+    let offset = center - point;
+    let distance = offset.magnitude();
+    let min_cos = if distance > radius {
+        (1.0 - (radius / distance).powi(2)).sqrt()
+    } else {
+        -1.0
+    };
+    let direction = sample_cone(offset / distance, min_cos);
+    let cos = direction.dot(&normal);
+    if cos <= 0.0 {
+        return None;
+    }
+    return Some((direction, cos * 2.0 * PI * (1.0 - min_cos)));
+}
+
+/// Uniformly distributed direction within the cone around `axis` whose
+/// directions have at least `min_cos` as cosine to the axis
+fn sample_cone(axis: Vector, min_cos: f64) -> Vector {
+    let (u, v) = basis(axis);
+    let z = 1.0 - rand01() * (1.0 - min_cos);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand01();
+    return u * (r * phi.cos()) + v * (r * phi.sin()) + axis * z;
+}
+
+/// Two unit vectors perpendicular to `w` and each other
+fn basis(w: Vector) -> (Vector, Vector) {
+    let u = (if w.x.abs() > 0.1 {
+        Vector::from(0.0, 1.0, 0.0)
+    } else {
+        Vector::from(1.0, 0.0, 0.0)
+    })
+    .cross(&w)
+    .normalize();
+    return (u, w.cross(&u));
+}
+
+fn luminance(v: Vector) -> f64 {
+    return 0.2126 * v.x + 0.7152 * v.y + 0.0722 * v.z;
+}
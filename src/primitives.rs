@@ -0,0 +1,242 @@
+use std::{f64::consts::PI, fmt::Display};
+
+use crate::{
+    mesh::{Mesh, Triangle},
+    Vector,
+};
+
+/// Parametric shape centered in the origin, turned into a mesh on demand.
+/// Written and parsed as a descriptor like `cylinder:0.5,2,32`, so scenes
+/// and command lines can keep the parameters instead of the triangles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    /// Axis aligned box
+    Box { size: Vector },
+    /// Closed cylinder along the y axis
+    Cylinder {
+        radius: f64,
+        height: f64,
+        /// Sides around the axis
+        segments: usize,
+    },
+    /// Ring around the y axis
+    Torus {
+        /// From the axis to the center of the tube
+        radius: f64,
+        tube_radius: f64,
+        /// Steps around the axis
+        segments: usize,
+        /// Steps around the tube
+        tube_segments: usize,
+    },
+    /// Rectangle in the xz plane, facing up
+    Plane {
+        width: f64,
+        depth: f64,
+        /// Quads along each side
+        subdivisions: usize,
+    },
+}
+
+impl Primitive {
+    /// Parses a descriptor: `box:<x>,<y>,<z>`,
+    /// `cylinder:<radius>,<height>[,<segments>]`,
+    /// `torus:<radius>,<tube radius>[,<segments>,<tube segments>]` or
+    /// `plane:<width>,<depth>[,<subdivisions>]`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (kind, params) = s.split_once(':')?;
+        let params: Vec<&str> = params.split(',').map(str::trim).collect();
+        let length = |i: usize| {
+            let value: f64 = params.get(i)?.parse().ok()?;
+            return (value.is_finite() && value > 0.0).then_some(value);
+        };
+        let count = |i: usize, default: usize, min: usize| match params.get(i) {
+            Some(param) => param.parse().ok().filter(|&n| n >= min),
+            None => Some(default),
+        };
+        let (primitive, param_count) = match kind {
+            "box" => (
+                Primitive::Box {
+                    size: Vector::from(length(0)?, length(1)?, length(2)?),
+                },
+                3,
+            ),
+            "cylinder" => (
+                Primitive::Cylinder {
+                    radius: length(0)?,
+                    height: length(1)?,
+                    segments: count(2, 32, 3)?,
+                },
+                3,
+            ),
+            "torus" => (
+                Primitive::Torus {
+                    radius: length(0)?,
+                    tube_radius: length(1)?,
+                    segments: count(2, 48, 3)?,
+                    tube_segments: count(3, 16, 3)?,
+                },
+                4,
+            ),
+            "plane" => (
+                Primitive::Plane {
+                    width: length(0)?,
+                    depth: length(1)?,
+                    subdivisions: count(2, 1, 1)?,
+                },
+                3,
+            ),
+            _ => return None,
+        };
+        if params.len() > param_count {
+            return None;
+        }
+        return Some(primitive);
+    }
+
+    pub fn to_mesh(&self) -> Mesh {
+        let mut triangles = Vec::new();
+        match *self {
+            Primitive::Box { size } => {
+                let half = size * 0.5;
+                let axes = [
+                    Vector::from(half.x, 0.0, 0.0),
+                    Vector::from(0.0, half.y, 0.0),
+                    Vector::from(0.0, 0.0, half.z),
+                ];
+                for k in 0..3 {
+                    for sign in [1.0, -1.0] {
+                        let (u, v) = (axes[(k + 1) % 3], axes[(k + 2) % 3] * sign);
+                        let corner = axes[k] * sign - u - v;
+                        grid(&mut triangles, corner, u * 2.0, v * 2.0, 1);
+                    }
+                }
+            }
+            Primitive::Cylinder {
+                radius,
+                height,
+                segments,
+            } => {
+                let (bottom, top) = (-0.5 * height, 0.5 * height);
+                let rim = |i: usize, y: f64| {
+                    let angle = 2.0 * PI * (i % segments) as f64 / segments as f64;
+                    return Vector::from(radius * angle.cos(), y, radius * angle.sin());
+                };
+                for i in 0..segments {
+                    quad(
+                        &mut triangles,
+                        [
+                            rim(i, bottom),
+                            rim(i, top),
+                            rim(i + 1, top),
+                            rim(i + 1, bottom),
+                        ],
+                    );
+                    triangles.push(Triangle {
+                        a: Vector::from(0.0, top, 0.0),
+                        b: rim(i + 1, top),
+                        c: rim(i, top),
+                    });
+                    triangles.push(Triangle {
+                        a: Vector::from(0.0, bottom, 0.0),
+                        b: rim(i, bottom),
+                        c: rim(i + 1, bottom),
+                    });
+                }
+            }
+            Primitive::Torus {
+                radius,
+                tube_radius,
+                segments,
+                tube_segments,
+            } => {
+                let point = |i: usize, j: usize| {
+                    let around = 2.0 * PI * (i % segments) as f64 / segments as f64;
+                    let tube = 2.0 * PI * (j % tube_segments) as f64 / tube_segments as f64;
+                    let distance = radius + tube_radius * tube.cos();
+                    return Vector::from(
+                        distance * around.cos(),
+                        tube_radius * tube.sin(),
+                        distance * around.sin(),
+                    );
+                };
+                for i in 0..segments {
+                    for j in 0..tube_segments {
+                        quad(
+                            &mut triangles,
+                            [
+                                point(i, j),
+                                point(i, j + 1),
+                                point(i + 1, j + 1),
+                                point(i + 1, j),
+                            ],
+                        );
+                    }
+                }
+            }
+            Primitive::Plane {
+                width,
+                depth,
+                subdivisions,
+            } => {
+                let (u, v) = (Vector::from(0.0, 0.0, depth), Vector::from(width, 0.0, 0.0));
+                grid(&mut triangles, (u + v) * -0.5, u, v, subdivisions);
+            }
+        }
+        return Mesh::from_triangles(&triangles);
+    }
+}
+
+impl Display for Primitive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Primitive::Box { size } => write!(f, "box:{},{},{}", size.x, size.y, size.z),
+            Primitive::Cylinder {
+                radius,
+                height,
+                segments,
+            } => write!(f, "cylinder:{},{},{}", radius, height, segments),
+            Primitive::Torus {
+                radius,
+                tube_radius,
+                segments,
+                tube_segments,
+            } => write!(
+                f,
+                "torus:{},{},{},{}",
+                radius, tube_radius, segments, tube_segments
+            ),
+            Primitive::Plane {
+                width,
+                depth,
+                subdivisions,
+            } => write!(f, "plane:{},{},{}", width, depth, subdivisions),
+        }
+    }
+}
+
+/// Two triangles for the corners of a quad, counterclockwise seen from the
+/// front
+fn quad(triangles: &mut Vec<Triangle>, [a, b, c, d]: [Vector; 4]) {
+    triangles.push(Triangle { a, b, c });
+    triangles.push(Triangle { a, b: c, c: d });
+}
+
+/// `n` by `n` quads covering the parallelogram spanned by `u` and `v` from
+/// `corner`, facing towards `u` cross `v`
+fn grid(triangles: &mut Vec<Triangle>, corner: Vector, u: Vector, v: Vector, n: usize) {
+    let point = |i: usize, j: usize| corner + u * (i as f64 / n as f64) + v * (j as f64 / n as f64);
+    for i in 0..n {
+        for j in 0..n {
+            quad(
+                triangles,
+                [
+                    point(i, j),
+                    point(i + 1, j),
+                    point(i + 1, j + 1),
+                    point(i, j + 1),
+                ],
+            );
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/// `count` points in the unit square, stratified in both axes (N-rooks):
+/// the square is split into `count` columns and `count` rows, and every
+/// column and every row holds exactly one point, jittered within its cell.
+/// Unlike a fixed grid this works for any sample count. `rand` returns
+/// uniform numbers in [0, 1). Points are generated as they are taken, so
+/// stopping early doesn't pay for the rest, and columns come in random
+/// order, so the points taken until then aren't bunched on one side.
+pub fn n_rooks(count: usize, mut rand: impl FnMut() -> f64) -> impl Iterator<Item = (f64, f64)> {
+    let mut columns: Vec<usize> = (0..count).collect();
+    let mut rows: Vec<usize> = (0..count).collect();
+    return (0..count).map(move |i| {
+        // Random order of columns and rows, one Fisher-Yates step at a time
+        let left = count - i;
+        let mut shuffle = |cells: &mut Vec<usize>| {
+            let j = i + ((rand() * left as f64) as usize).min(left - 1);
+            cells.swap(i, j);
+            return cells[i];
+        };
+        let (column, row) = (shuffle(&mut columns), shuffle(&mut rows));
+        let x = (column as f64 + rand()) / count as f64;
+        let y = (row as f64 + rand()) / count as f64;
+        (x, y)
+    });
+}
+
+/// Picks indices with probability proportional to fixed weights in constant
+/// time, however many there are (Vose's alias method). Each bin holds its
+/// own index and at most one alias, which fills the rest of the bin.
+#[derive(Clone, Debug)]
+pub struct AliasTable {
+    /// Chance of keeping the bin's own index instead of its alias
+    probability: Vec<f64>,
+    alias: Vec<u32>,
+}
+
+impl AliasTable {
+    /// Table for picking index `i` with probability proportional to
+    /// `weights[i]`. Uniform if all weights are zero.
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().sum();
+        let mut scaled: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|w| w * n as f64 / total).collect()
+        } else {
+            vec![1.0; n]
+        };
+        let mut probability = vec![1.0; n];
+        let mut alias: Vec<u32> = (0..n as u32).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| scaled[i] < 1.0);
+        // Fill each underfull bin from an overfull one
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            probability[s] = scaled[s];
+            alias[s] = l as u32;
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Bins left over are full up to rounding and keep their own index
+        return AliasTable { probability, alias };
+    }
+
+    /// Picks an index for `u` in [0, 1). Also returns a fresh uniform number
+    /// in [0, 1) made from the bits of `u` that picking didn't use.
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        let scaled = u * self.probability.len() as f64;
+        let bin = (scaled as usize).min(self.probability.len() - 1);
+        let coin = scaled - bin as f64;
+        let keep = self.probability[bin];
+        return if coin < keep {
+            (bin, coin / keep)
+        } else {
+            (self.alias[bin] as usize, (coin - keep) / (1.0 - keep))
+        };
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{SceneObject, SceneObjectData, Vector};
+
+/// Placement of a node relative to its parent: scaled, then rotated, then
+/// moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector,
+    /// Rotation in degrees around the x, then y, then z axis
+    pub rotation: Vector,
+    /// Uniform, so that spheres and discs stay round
+    pub scale: f64,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        return Transform::translation(Vector::zero());
+    }
+
+    pub fn translation(translation: Vector) -> Self {
+        return Transform {
+            translation,
+            rotation: Vector::zero(),
+            scale: 1.0,
+        };
+    }
+
+    pub fn apply_point(&self, v: Vector) -> Vector {
+        return self.apply_vector(v) + self.translation;
+    }
+
+    /// Like `apply_point`, but without the translation, for offsets and normals
+    pub fn apply_vector(&self, v: Vector) -> Vector {
+        return (v * self.scale).rotate_xyz(self.rotation);
+    }
+}
+
+/// Part of a composite model. Objects and child nodes are placed relative
+/// to the node.
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+    pub name: String,
+    pub transform: Transform,
+    /// Objects with positions in the node's coordinates
+    pub objects: Vec<SceneObjectData>,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    /// Node without objects that groups `children`
+    pub fn group(name: &str, transform: Transform, children: Vec<SceneNode>) -> Self {
+        return SceneNode {
+            name: name.to_owned(),
+            transform,
+            objects: Vec::new(),
+            children,
+        };
+    }
+
+    /// Objects of this node and all descendants in scene coordinates, for
+    /// `SceneData.objects`. Unnamed objects are named after their node path,
+    /// like `car/wheels/front-left`, with the index within the node appended
+    /// if it holds several objects.
+    pub fn flatten(&self) -> Vec<SceneObjectData> {
+        let mut objects = Vec::new();
+        self.flatten_into(&mut Vec::new(), "", &mut objects);
+        return objects;
+    }
+
+    fn flatten_into(
+        &self,
+        ancestors: &mut Vec<Transform>,
+        parent_path: &str,
+        objects: &mut Vec<SceneObjectData>,
+    ) {
+        let path = if parent_path.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}/{}", parent_path, self.name)
+        };
+        ancestors.push(self.transform);
+        // Innermost transform first
+        let point = |v: Vector| ancestors.iter().rev().fold(v, |v, t| t.apply_point(v));
+        let vector = |v: Vector| ancestors.iter().rev().fold(v, |v, t| t.apply_vector(v));
+        let scale: f64 = ancestors.iter().map(|t| t.scale).product();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let type_ = match &object.type_ {
+                SceneObject::Sphere { radius } => SceneObject::Sphere {
+                    radius: radius * scale,
+                },
+                SceneObject::Plane { normal } => SceneObject::Plane {
+                    normal: vector(*normal).normalize(),
+                },
+                SceneObject::Disc { normal, radius } => SceneObject::Disc {
+                    normal: vector(*normal).normalize(),
+                    radius: radius * scale,
+                },
+                SceneObject::Mesh(mesh) => SceneObject::Mesh(mesh.transformed(vector)),
+            };
+            let name = object.name.clone().unwrap_or_else(|| {
+                if self.objects.len() == 1 {
+                    path.clone()
+                } else {
+                    format!("{}/{}", path, i)
+                }
+            });
+            objects.push(SceneObjectData {
+                type_,
+                position: point(object.position),
+                material: object.material.clone(),
+                visibility: object.visibility,
+                name: Some(name),
+            });
+        }
+        for child in &self.children {
+            child.flatten_into(ancestors, &path, objects);
+        }
+        ancestors.pop();
+    }
+}
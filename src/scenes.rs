@@ -1,9 +1,29 @@
+use std::sync::Arc;
+
 use crate::{
-    load_off::load_off, CameraData, Material, ReflectType, SceneData, SceneObject, SceneObjectData,
-    Vector,
+    animation::{Animation, Easing, Keyframe},
+    env_map::load_hdr,
+    light::{Light, LightShape},
+    load_off::load_off,
+    medium::Medium,
+    mesh::{Mesh, Triangle},
+    scene_graph::{SceneNode, Transform},
+    sky::Sky,
+    with_path, CameraData, Environment, Material, MaterialOverride, ReflectType, RenderSettings,
+    SceneData, SceneObject, SceneObjectData, SharedMaterial, Vector, Visibility,
 };
 
-pub fn load_scenes() -> Vec<SceneData> {
+/// Builtin scenes. Fails if a mesh or environment map file can't be loaded.
+pub fn load_scenes() -> Result<Vec<SceneData>, std::io::Error> {
+    return load_scenes_each()
+        .into_iter()
+        .map(|(_, scene)| scene)
+        .collect();
+}
+
+/// Builtin scenes with their ids, each loaded on its own, so a missing mesh
+/// or environment map only fails the scenes that use it.
+pub fn load_scenes_each() -> Vec<(String, Result<SceneData, std::io::Error>)> {
     // Set up scene
     const BOX_DIMENSIONS: Vector = Vector {
         x: 2.6,
@@ -21,7 +41,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::from(0.85, 0.25, 0.25),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("left wall".to_owned()),
         },
         // Right
         SceneObjectData {
@@ -31,7 +55,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::from(0.25, 0.35, 0.85),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("right wall".to_owned()),
         },
         // Top
         SceneObjectData {
@@ -41,7 +69,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::from(0.75, 0.75, 0.75),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("ceiling".to_owned()),
         },
         // Bottom
         SceneObjectData {
@@ -51,7 +83,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::from(0.75, 0.75, 0.75),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("floor".to_owned()),
         },
         // Back
         SceneObjectData {
@@ -61,7 +97,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::from(0.75, 0.75, 0.75),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("back wall".to_owned()),
         },
         // Front
         SceneObjectData {
@@ -71,7 +111,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 color: Vector::zero(),
                 emmission: Vector::zero(),
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("front wall".to_owned()),
         },
         // The ceiling area light source (slightly yellowish color)
         SceneObjectData {
@@ -82,7 +126,11 @@ pub fn load_scenes() -> Vec<SceneData> {
                 // emmission: Vector::from(0.98 * 2.0, 2.0, 0.9 * 2.0),
                 emmission: Vector::from(0.98, 1.0, 0.9) * 15.0,
                 reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
             },
+            visibility: Visibility::Visible,
+            name: Some("light".to_owned()),
         },
     ];
 
@@ -92,127 +140,823 @@ pub fn load_scenes() -> Vec<SceneData> {
         focal_length: 0.035,
     };
 
-    // scene_id to scene_objects
-    return vec![
-        SceneData {
-            id: "single-sphere".to_owned(),
-            objects: vec![SceneObjectData {
-                position: Vector::from(0.0, 0.0, 0.0),
-                type_: SceneObject::Sphere { radius: 1.0 },
-                material: Material {
-                    color: Vector::from(1.0, 1.0, 1.0),
-                    emmission: Vector::from(0.98 * 15.0, 15.0, 0.9 * 15.0),
-                    reflect_type: ReflectType::Diffuse,
-                },
-            }],
-            camera: default_camera,
-        },
-        SceneData {
-            id: "two-spheres".to_owned(),
-            objects: vec![
-                SceneObjectData {
+    // Shared materials of the carts
+    let paint = Material {
+        color: Vector::from(0.25, 0.35, 0.85),
+        emmission: Vector::zero(),
+        reflect_type: ReflectType::Diffuse,
+        vertex_colors: false,
+        texture: None,
+    };
+    let rubber = Material {
+        color: Vector::uniform(0.15),
+        ..paint.clone()
+    };
+
+    // scene_id to scene_objects. Scenes can also be selected by their index,
+    // so new ones go at the end.
+    let mut scenes = vec![
+        scene("single-sphere", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![SceneObjectData {
                     position: Vector::from(0.0, 0.0, 0.0),
                     type_: SceneObject::Sphere { radius: 1.0 },
                     material: Material {
-                        color: Vector::from(1.0, 0.0, 0.0),
-                        emmission: Vector::from(0.0, 0.0, 0.0),
+                        color: Vector::from(1.0, 1.0, 1.0),
+                        emmission: Vector::from(0.98 * 15.0, 15.0, 0.9 * 15.0),
                         reflect_type: ReflectType::Diffuse,
+                        vertex_colors: false,
+                        texture: None,
                     },
-                },
-                SceneObjectData {
-                    position: Vector::from(0.0, 0.0, 10.0),
-                    type_: SceneObject::Sphere { radius: 1.0 },
-                    material: Material {
-                        color: Vector::from(0.0, 0.0, 0.0),
-                        emmission: Vector::uniform(10.0),
-                        reflect_type: ReflectType::Diffuse,
+                    visibility: Visibility::Visible,
+                    name: None,
+                }],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("two-spheres", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    SceneObjectData {
+                        position: Vector::from(0.0, 0.0, 0.0),
+                        type_: SceneObject::Sphere { radius: 1.0 },
+                        material: Material {
+                            color: Vector::from(1.0, 0.0, 0.0),
+                            emmission: Vector::from(0.0, 0.0, 0.0),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
                     },
-                },
-            ],
-            camera: default_camera,
-        },
-        SceneData {
-            id: "three-spheres".to_owned(),
-            objects: vec![
-                SceneObjectData {
-                    position: Vector::from(0.0, 0.0, -3.0),
-                    type_: SceneObject::Sphere { radius: 1.0 },
-                    material: Material {
-                        color: Vector::from(1.0, 0.2, 0.2),
-                        emmission: Vector::from(0.0, 0.0, 0.0),
-                        reflect_type: ReflectType::Diffuse,
+                    SceneObjectData {
+                        position: Vector::from(0.0, 0.0, 10.0),
+                        type_: SceneObject::Sphere { radius: 1.0 },
+                        material: Material {
+                            color: Vector::from(0.0, 0.0, 0.0),
+                            emmission: Vector::uniform(10.0),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
                     },
-                },
-                SceneObjectData {
-                    position: Vector::from(4.0, 2.0, 0.0),
-                    type_: SceneObject::Sphere { radius: 1.0 },
+                ],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("three-spheres", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    SceneObjectData {
+                        position: Vector::from(0.0, 0.0, -3.0),
+                        type_: SceneObject::Sphere { radius: 1.0 },
+                        material: Material {
+                            color: Vector::from(1.0, 0.2, 0.2),
+                            emmission: Vector::from(0.0, 0.0, 0.0),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(4.0, 2.0, 0.0),
+                        type_: SceneObject::Sphere { radius: 1.0 },
+                        material: Material {
+                            color: Vector::from(0.0, 0.0, 0.0),
+                            emmission: Vector::from(20.0, 10.0, 10.0),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(-6.0, -2.0, 0.0),
+                        type_: SceneObject::Sphere { radius: 1.0 },
+                        material: Material {
+                            color: Vector::from(0.0, 0.0, 0.0),
+                            emmission: Vector::from(5.0, 9.0, 20.0),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                ],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("cornell", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    // Objects
+                    // mirroring
+                    SceneObjectData {
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        position: Vector::from(-1.3, -BOX_DIMENSIONS.y + 0.8, -1.3),
+                        material: Material {
+                            color: Vector::uniform(0.999),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Specular,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: Some("mirror sphere".to_owned()),
+                    },
+                    // refracting
+                    SceneObjectData {
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        position: Vector::from(1.3, -BOX_DIMENSIONS.y + 0.8, -0.2),
+                        material: Material {
+                            color: Vector::uniform(0.999),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Refract {
+                                ior: 1.5,
+                                absorption: Vector::zero(),
+                            },
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: Some("glass sphere".to_owned()),
+                    },
+                ]
+                .into_iter()
+                .chain(cornell_box.clone())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: vec![
+                    (
+                        "mirror".to_owned(),
+                        CameraData {
+                            position: Vector::from(0.2, -0.6, 1.5),
+                            direction: Vector::from(-1.5, -0.6, -2.8),
+                            focal_length: 0.035,
+                        },
+                    ),
+                    (
+                        "glass".to_owned(),
+                        CameraData {
+                            position: Vector::from(-0.6, -0.3, 3.6),
+                            direction: Vector::from(1.9, -0.9, -3.8),
+                            focal_length: 0.05,
+                        },
+                    ),
+                ],
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("mesh", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![SceneObjectData {
+                    position: Vector::from(-0.8, -BOX_DIMENSIONS.y + 0.5, 0.0),
+                    type_: SceneObject::Mesh(with_path(
+                        load_off("meshes/mctri.off", 0.16),
+                        "meshes/mctri.off",
+                    )?),
                     material: Material {
-                        color: Vector::from(0.0, 0.0, 0.0),
-                        emmission: Vector::from(20.0, 10.0, 10.0),
+                        color: Vector::from(234.0 / 255.0, 1.0, 0.0),
+                        emmission: Vector::zero(),
                         reflect_type: ReflectType::Diffuse,
+                        vertex_colors: false,
+                        texture: None,
                     },
+                    visibility: Visibility::Visible,
+                    name: None,
+                }]
+                .into_iter()
+                .chain(cornell_box.clone())
+                .collect(),
+                camera: CameraData {
+                    position: Vector::from(
+                        0.9,
+                        0.26 * BOX_DIMENSIONS.y,
+                        3.0 * BOX_DIMENSIONS.z - 1.0,
+                    ),
+                    direction: Vector::from(-0.09, -0.06, -1.0),
+                    focal_length: 0.035,
                 },
-                SceneObjectData {
-                    position: Vector::from(-6.0, -2.0, 0.0),
-                    type_: SceneObject::Sphere { radius: 1.0 },
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("plane-disc", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    // Floor
+                    SceneObjectData {
+                        position: Vector::from(0.0, -BOX_DIMENSIONS.y, 0.0),
+                        type_: SceneObject::Plane {
+                            normal: Vector::from(0.0, 1.0, 0.0),
+                        },
+                        material: Material {
+                            color: Vector::uniform(0.75),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(0.0, -BOX_DIMENSIONS.y + 0.8, -1.0),
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        material: Material {
+                            color: Vector::from(0.85, 0.25, 0.25),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    // Disc area light facing down
+                    SceneObjectData {
+                        position: Vector::from(0.0, BOX_DIMENSIONS.y, -1.0),
+                        type_: SceneObject::Disc {
+                            normal: Vector::from(0.0, -1.0, 0.0),
+                            radius: 1.5,
+                        },
+                        material: Material {
+                            color: Vector::zero(),
+                            emmission: Vector::from(0.98, 1.0, 0.9) * 10.0,
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                ],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("bouncing-sphere", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![SceneObjectData {
+                    position: Vector::from(0.0, -BOX_DIMENSIONS.y + 0.6, -1.0),
+                    type_: SceneObject::Sphere { radius: 0.6 },
                     material: Material {
-                        color: Vector::from(0.0, 0.0, 0.0),
-                        emmission: Vector::from(5.0, 9.0, 20.0),
+                        color: Vector::from(0.25, 0.85, 0.35),
+                        emmission: Vector::zero(),
                         reflect_type: ReflectType::Diffuse,
+                        vertex_colors: false,
+                        texture: None,
                     },
+                    visibility: Visibility::Visible,
+                    name: None,
+                }]
+                .into_iter()
+                .chain(cornell_box.clone())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+                animation: Animation {
+                    camera_position: vec![
+                        Keyframe {
+                            frame: 0,
+                            value: default_camera.position,
+                            easing: Easing::Linear,
+                        },
+                        Keyframe {
+                            frame: 48,
+                            value: default_camera.position - Vector::from(0.0, 0.0, 2.0),
+                            easing: Easing::EaseInOut,
+                        },
+                    ],
+                    camera_direction: vec![],
+                    object_positions: vec![(
+                        0,
+                        [0, 12, 24, 36, 48]
+                            .iter()
+                            .enumerate()
+                            .map(|(i, frame)| Keyframe {
+                                frame: *frame,
+                                value: Vector::from(
+                                    -1.2 + 0.6 * i as f64,
+                                    -BOX_DIMENSIONS.y + if i % 2 == 0 { 0.6 } else { 1.8 },
+                                    -1.0,
+                                ),
+                                easing: Easing::Linear,
+                            })
+                            .collect(),
+                    )],
                 },
-            ],
-            camera: default_camera,
-        },
-        SceneData {
-            id: "cornell".to_owned(),
-            objects: vec![
-                // Objects
-                // mirroring
-                SceneObjectData {
+            })
+        }),
+        scene("sky", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    // Ground
+                    SceneObjectData {
+                        position: Vector::from(0.0, -BOX_DIMENSIONS.y, 0.0),
+                        type_: SceneObject::Plane {
+                            normal: Vector::from(0.0, 1.0, 0.0),
+                        },
+                        material: Material {
+                            color: Vector::uniform(0.5),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(-1.3, -BOX_DIMENSIONS.y + 0.8, -1.3),
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        material: Material {
+                            color: Vector::uniform(0.999),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Specular,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(1.3, -BOX_DIMENSIONS.y + 0.8, -0.2),
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        material: Material {
+                            color: Vector::from(0.85, 0.25, 0.25),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                ],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Sky(Sky::new(25.0, 60.0, 3.0, 0.05)),
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("studio-hdri", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    SceneObjectData {
+                        position: Vector::from(-1.0, -BOX_DIMENSIONS.y + 0.8, -1.0),
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        material: Material {
+                            color: Vector::uniform(0.75),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        position: Vector::from(1.0, -BOX_DIMENSIONS.y + 0.8, -1.0),
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        material: Material {
+                            color: Vector::uniform(0.999),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Specular,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    // Ground
+                    SceneObjectData {
+                        position: Vector::from(0.0, -BOX_DIMENSIONS.y, 0.0),
+                        type_: SceneObject::Disc {
+                            normal: Vector::from(0.0, 1.0, 0.0),
+                            radius: 4.0,
+                        },
+                        material: Material {
+                            color: Vector::uniform(0.6),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                ],
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Map(Arc::new(with_path(
+                    load_hdr("envmaps/studio.hdr", 1.0),
+                    "envmaps/studio.hdr",
+                )?)),
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("cornell-fog", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![SceneObjectData {
                     type_: SceneObject::Sphere { radius: 0.8 },
-                    position: Vector::from(-1.3, -BOX_DIMENSIONS.y + 0.8, -1.3),
+                    position: Vector::from(0.0, -BOX_DIMENSIONS.y + 0.8, -0.8),
                     material: Material {
-                        color: Vector::uniform(0.999),
+                        color: Vector::uniform(0.75),
                         emmission: Vector::zero(),
-                        reflect_type: ReflectType::Specular,
+                        reflect_type: ReflectType::Diffuse,
+                        vertex_colors: false,
+                        texture: None,
                     },
-                },
-                // refracting
-                SceneObjectData {
-                    type_: SceneObject::Sphere { radius: 0.8 },
-                    position: Vector::from(1.3, -BOX_DIMENSIONS.y + 0.8, -0.2),
+                    visibility: Visibility::Visible,
+                    name: None,
+                }]
+                .into_iter()
+                .chain(cornell_box.clone())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: Some(Medium {
+                    sigma_a: 0.01,
+                    sigma_s: 0.12,
+                    g: 0.6,
+                }),
+            })
+        }),
+        scene("mesh-light", |id| {
+            Ok(SceneData {
+                id,
+                objects: vec![
+                    SceneObjectData {
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        position: Vector::from(-1.1, -BOX_DIMENSIONS.y + 0.8, -1.0),
+                        material: Material {
+                            color: Vector::uniform(0.75),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    SceneObjectData {
+                        type_: SceneObject::Sphere { radius: 0.8 },
+                        position: Vector::from(1.1, -BOX_DIMENSIONS.y + 0.8, -0.4),
+                        material: Material {
+                            color: Vector::uniform(0.999),
+                            emmission: Vector::zero(),
+                            reflect_type: ReflectType::Specular,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                    // Square ceiling light made of two triangles
+                    SceneObjectData {
+                        type_: SceneObject::Mesh(Mesh::from_triangles(&[
+                            Triangle {
+                                a: Vector::from(-0.6, 0.0, -0.6),
+                                b: Vector::from(0.6, 0.0, -0.6),
+                                c: Vector::from(0.6, 0.0, 0.6),
+                            },
+                            Triangle {
+                                a: Vector::from(-0.6, 0.0, -0.6),
+                                b: Vector::from(0.6, 0.0, 0.6),
+                                c: Vector::from(-0.6, 0.0, 0.6),
+                            },
+                        ])),
+                        position: Vector::from(0.0, BOX_DIMENSIONS.y - 0.05, -0.5),
+                        material: Material {
+                            color: Vector::zero(),
+                            emmission: Vector::from(0.98, 1.0, 0.9) * 12.0,
+                            reflect_type: ReflectType::Diffuse,
+                            vertex_colors: false,
+                            texture: None,
+                        },
+                        visibility: Visibility::Visible,
+                        name: None,
+                    },
+                ]
+                .into_iter()
+                // Without the spherical ceiling light
+                .chain(cornell_box[..cornell_box.len() - 1].iter().cloned())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("carts", |id| {
+            Ok(SceneData {
+                id,
+                objects: cart(
+                    "cart",
+                    Transform::translation(Vector::from(-0.9, -BOX_DIMENSIONS.y, -1.2)),
+                    &paint,
+                    &rubber,
+                )
+                .flatten()
+                .into_iter()
+                // The same model again, turned and shrunk
+                .chain(
+                    cart(
+                        "small cart",
+                        Transform {
+                            translation: Vector::from(1.1, -BOX_DIMENSIONS.y, -0.2),
+                            rotation: Vector::from(0.0, -40.0, 0.0),
+                            scale: 0.7,
+                        },
+                        &paint,
+                        &rubber,
+                    )
+                    .flatten(),
+                )
+                .chain(cornell_box.clone())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: vec![
+                    SharedMaterial {
+                        name: "paint".to_owned(),
+                        material: paint.clone(),
+                        // The small cart keeps its own color when the paint changes
+                        users: vec![
+                            (0, MaterialOverride::default()),
+                            (
+                                5,
+                                MaterialOverride {
+                                    color: Some(Vector::from(0.85, 0.6, 0.2)),
+                                    ..Default::default()
+                                },
+                            ),
+                        ],
+                    },
+                    SharedMaterial {
+                        name: "rubber".to_owned(),
+                        material: rubber.clone(),
+                        users: [1, 2, 3, 4, 6, 7, 8, 9]
+                            .map(|i| (i, MaterialOverride::default()))
+                            .to_vec(),
+                    },
+                ],
+                lights: Vec::new(),
+                caustics: None,
+                medium: None,
+            })
+        }),
+        scene("lights", |id| {
+            Ok(SceneData {
+                id,
+                objects: [
+                    (-0.9, Vector::uniform(0.75)),
+                    (0.9, Vector::from(0.85, 0.6, 0.2)),
+                ]
+                .iter()
+                .map(|&(x, color)| SceneObjectData {
+                    position: Vector::from(x, -BOX_DIMENSIONS.y + 0.6, -0.8),
+                    type_: SceneObject::Sphere { radius: 0.6 },
                     material: Material {
-                        color: Vector::uniform(0.999),
+                        color,
                         emmission: Vector::zero(),
-                        reflect_type: ReflectType::Refract,
+                        reflect_type: ReflectType::Diffuse,
+                        vertex_colors: false,
+                        texture: None,
+                    },
+                    visibility: Visibility::Visible,
+                    name: None,
+                })
+                // Without the spherical ceiling light
+                .chain(cornell_box[..cornell_box.len() - 1].iter().cloned())
+                .collect(),
+                camera: default_camera,
+                camera_bookmarks: Vec::new(),
+                animation: Animation::default(),
+                environment: Environment::Black,
+                clip_plane: None,
+                render_settings: RenderSettings::default(),
+                materials: Vec::new(),
+                lights: vec![
+                    Light {
+                        position: Vector::from(0.0, BOX_DIMENSIONS.y - 0.01, -0.5),
+                        emission: Vector::from(0.98, 1.0, 0.9) * 8.0,
+                        // Facing down
+                        shape: LightShape::Area {
+                            u: Vector::from(1.2, 0.0, 0.0),
+                            v: Vector::from(0.0, 0.0, 1.2),
+                        },
+                        name: Some("ceiling".to_owned()),
+                    },
+                    Light {
+                        position: Vector::from(-2.2, 1.6, 1.5),
+                        emission: Vector::from(0.6, 0.8, 1.0) * 12.0,
+                        shape: LightShape::Spot {
+                            direction: Vector::from(1.3, -3.0, -2.3),
+                            cone_angle: 15.0,
+                            falloff: 5.0,
+                        },
+                        name: Some("spot".to_owned()),
+                    },
+                    Light {
+                        position: Vector::from(2.0, 0.5, 1.5),
+                        emission: Vector::from(1.0, 0.7, 0.4) * 0.8,
+                        shape: LightShape::Point,
+                        name: Some("bulb".to_owned()),
                     },
+                ],
+                caustics: None,
+                medium: None,
+            })
+        }),
+    ];
+    for (_, scene) in &mut scenes {
+        if let Ok(scene) = scene {
+            scene.update_materials();
+        }
+    }
+    return scenes;
+}
+
+/// Builds the scene `id`, keeping the id if a file it needs can't be loaded
+fn scene(
+    id: &str,
+    build: impl FnOnce(String) -> Result<SceneData, std::io::Error>,
+) -> (String, Result<SceneData, std::io::Error>) {
+    return (id.to_owned(), build(id.to_owned()));
+}
+
+/// Toy cart standing on the origin of `transform`: a box with four wheels.
+/// Its objects are the body, followed by the wheels.
+fn cart(name: &str, transform: Transform, paint: &Material, rubber: &Material) -> SceneNode {
+    let wheel = |name: &str, x: f64, z: f64| SceneNode {
+        name: name.to_owned(),
+        transform: Transform::translation(Vector::from(x, 0.2, z)),
+        objects: vec![SceneObjectData {
+            type_: SceneObject::Sphere { radius: 0.2 },
+            position: Vector::zero(),
+            material: rubber.clone(),
+            visibility: Visibility::Visible,
+            name: None,
+        }],
+        children: Vec::new(),
+    };
+    return SceneNode {
+        name: name.to_owned(),
+        transform,
+        objects: vec![SceneObjectData {
+            type_: SceneObject::Mesh(cuboid(Vector::from(0.6, 0.2, 0.35))),
+            position: Vector::from(0.0, 0.45, 0.0),
+            material: paint.clone(),
+            visibility: Visibility::Visible,
+            name: None,
+        }],
+        children: vec![SceneNode::group(
+            "wheels",
+            Transform::identity(),
+            vec![
+                wheel("front-left", -0.4, 0.38),
+                wheel("front-right", 0.4, 0.38),
+                wheel("back-left", -0.4, -0.38),
+                wheel("back-right", 0.4, -0.38),
+            ],
+        )],
+    };
+}
+
+/// Box centered on the origin with outward facing triangles
+fn cuboid(half_size: Vector) -> Mesh {
+    let x = Vector::from(half_size.x, 0.0, 0.0);
+    let y = Vector::from(0.0, half_size.y, 0.0);
+    let z = Vector::from(0.0, 0.0, half_size.z);
+    // Face center and two edges whose cross product points outwards
+    let faces = [
+        (x, y, z),
+        (x * -1.0, z, y),
+        (y, z, x),
+        (y * -1.0, x, z),
+        (z, x, y),
+        (z * -1.0, y, x),
+    ];
+    let triangles = faces
+        .iter()
+        .flat_map(|&(c, u, v)| {
+            [
+                Triangle {
+                    a: c - u - v,
+                    b: c + u - v,
+                    c: c + u + v,
                 },
-            ]
-            .into_iter()
-            .chain(cornell_box.clone())
-            .collect(),
-            camera: default_camera,
-        },
-        SceneData {
-            id: "mesh".to_owned(),
-            objects: vec![SceneObjectData {
-                position: Vector::from(-0.8, -BOX_DIMENSIONS.y + 0.5, 0.0),
-                type_: SceneObject::Mesh(load_off("meshes/mctri.off", 0.16).unwrap()),
-                material: Material {
-                    color: Vector::from(234.0 / 255.0, 1.0, 0.0),
-                    emmission: Vector::zero(),
-                    reflect_type: ReflectType::Diffuse,
+                Triangle {
+                    a: c - u - v,
+                    b: c + u + v,
+                    c: c - u + v,
                 },
-            }]
-            .into_iter()
-            .chain(cornell_box.clone())
-            .collect(),
-            camera: CameraData {
-                position: Vector::from(0.9, 0.26 * BOX_DIMENSIONS.y, 3.0 * BOX_DIMENSIONS.z - 1.0),
-                direction: Vector::from(-0.09, -0.06, -1.0),
-                focal_length: 0.035,
-            },
-        },
-    ];
+            ]
+        })
+        .collect::<Vec<_>>();
+    return Mesh::from_triangles(&triangles);
 }
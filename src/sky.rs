@@ -0,0 +1,123 @@
+use crate::Vector;
+
+/// Coefficients of the Perez sky luminance distribution
+#[derive(Clone, Copy, Debug)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// `cos_theta` is the cosine of the view direction's zenith angle, `gamma` the angle
+    /// between view and sun direction.
+    fn f(&self, cos_theta: f64, gamma: f64) -> f64 {
+        return (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * gamma.cos().powi(2));
+    }
+}
+
+/// Analytic daylight model from Preetham, Shirley and Smits,
+/// "A Practical Analytic Model for Daylight" (1999). Y is up.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    sun_direction: Vector,
+    /// Scales the sky luminance, which is given in kcd/m² by the model
+    intensity: f64,
+    perez_y: Perez,
+    perez_x: Perez,
+    perez_yy: Perez,
+    /// Zenith luminance and chromaticity, divided by the Perez function at the zenith
+    zenith: Vector,
+}
+
+impl Sky {
+    /// Angles are in degrees, the azimuth is measured from +z towards +x.
+    /// Turbidity describes the haziness of the atmosphere, from 2 (clear) to
+    /// about 10 (hazy).
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, intensity: f64) -> Self {
+        let (elevation, azimuth) = (sun_elevation.to_radians(), sun_azimuth.to_radians());
+        let sun_direction = Vector::from(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos(),
+        );
+        let t = turbidity;
+        let theta_s = std::f64::consts::FRAC_PI_2 - elevation;
+
+        let perez_y = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_yy = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f64::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (th, th2, th3) = (theta_s, theta_s.powi(2), theta_s.powi(3));
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        return Sky {
+            sun_direction,
+            intensity,
+            perez_y,
+            perez_x,
+            perez_yy,
+            zenith: Vector::from(
+                zenith_luminance / perez_y.f(1.0, theta_s),
+                zenith_x / perez_x.f(1.0, theta_s),
+                zenith_y / perez_yy.f(1.0, theta_s),
+            ),
+        };
+    }
+
+    /// Linear sRGB radiance of the sky seen in `direction`. Black below the horizon.
+    pub fn radiance(&self, direction: Vector) -> Vector {
+        if direction.y <= 0.0 {
+            return Vector::zero();
+        }
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith.x * self.perez_y.f(cos_theta, gamma);
+        let x = self.zenith.y * self.perez_x.f(cos_theta, gamma);
+        let y = self.zenith.z * self.perez_yy.f(cos_theta, gamma);
+
+        return xyy_to_linear_srgb(x, y, luminance * self.intensity);
+    }
+}
+
+fn xyy_to_linear_srgb(x: f64, y: f64, luminance: f64) -> Vector {
+    if y <= 0.0 {
+        return Vector::zero();
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    return Vector::from(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    );
+}
@@ -0,0 +1,97 @@
+use std::f64::consts::PI;
+
+use crate::Vector;
+
+/// Procedural albedo that blends between the material color and `color`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Texture {
+    pub pattern: Pattern,
+    pub mapping: Mapping,
+    /// Second color of the pattern, the first one is the material color
+    pub color: Vector,
+    /// Pattern repetitions per scene unit, or around the object for
+    /// `Mapping::Spherical`
+    pub scale: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// Alternating cubes of the two colors
+    Checker,
+    /// Linear ramp upwards, repeating every `1 / scale`
+    Gradient,
+    /// Smooth value noise
+    Noise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    /// Evaluated at the hit position in scene coordinates
+    World,
+    /// Longitude and latitude around the object position, which are the UV
+    /// coordinates of spheres
+    Spherical,
+}
+
+impl Texture {
+    /// Albedo at `point` on an object at `center` with the material color `base`.
+    pub fn albedo(&self, base: Vector, point: Vector, center: Vector) -> Vector {
+        let p = match self.mapping {
+            Mapping::World => point * self.scale,
+            Mapping::Spherical => {
+                let (u, v) = sphere_uv((point - center).normalize());
+                // Half as many rows as columns keeps checkers square at the equator
+                Vector::from(u * self.scale, v * self.scale / 2.0, 0.0)
+            }
+        };
+        let t = match self.pattern {
+            Pattern::Checker => {
+                let cells = p.x.floor() + p.y.floor() + p.z.floor();
+                if cells.rem_euclid(2.0) < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Pattern::Gradient => p.y.rem_euclid(1.0),
+            Pattern::Noise => value_noise(p),
+        };
+        return base * (1.0 - t) + self.color * t;
+    }
+}
+
+/// UV coordinates in [0, 1] of the unit direction `d` from a sphere's
+/// center, with v = 1 at the top.
+pub fn sphere_uv(d: Vector) -> (f64, f64) {
+    let u = 0.5 + d.z.atan2(d.x) / (2.0 * PI);
+    let v = 0.5 + d.y.clamp(-1.0, 1.0).asin() / PI;
+    return (u, v);
+}
+
+/// Random value in [0, 1) for each integer lattice point
+fn lattice_value(x: f64, y: f64, z: f64) -> f64 {
+    let mut h = (x as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as i64 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as i64 as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 32;
+    return (h >> 11) as f64 / (1u64 << 53) as f64;
+}
+
+/// Trilinear interpolation of the lattice values with smoothstep weights
+fn value_noise(p: Vector) -> f64 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (tx, ty, tz) = (smooth(p.x - x0), smooth(p.y - y0), smooth(p.z - z0));
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let corner = |dx: f64, dy: f64, dz: f64| lattice_value(x0 + dx, y0 + dy, z0 + dz);
+    let face = |dz: f64| {
+        lerp(
+            lerp(corner(0.0, 0.0, dz), corner(1.0, 0.0, dz), tx),
+            lerp(corner(0.0, 1.0, dz), corner(1.0, 1.0, dz), tx),
+            ty,
+        )
+    };
+    return lerp(face(0.0), face(1.0), tz);
+}
@@ -0,0 +1,44 @@
+use crate::Vector;
+
+/// Maps scene radiance to displayable values in [0, 1], before gamma correction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tonemap {
+    /// Clip everything above 1
+    Clamp,
+    /// Reinhard, x / (1 + x) per channel
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+}
+
+impl Tonemap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "clamp" => Some(Tonemap::Clamp),
+            "reinhard" => Some(Tonemap::Reinhard),
+            "aces" => Some(Tonemap::AcesFilmic),
+            _ => None,
+        };
+    }
+
+    /// Applies `exposure` (in stops) and the tonemapping curve to `radiance`.
+    pub fn apply(&self, radiance: Vector, exposure: f64) -> Vector {
+        let v = radiance * 2f64.powf(exposure);
+        let mapped = match self {
+            Tonemap::Clamp => v,
+            Tonemap::Reinhard => {
+                let reinhard = |x: f64| x / (1.0 + x);
+                Vector::from(reinhard(v.x), reinhard(v.y), reinhard(v.z))
+            }
+            Tonemap::AcesFilmic => {
+                let aces = |x: f64| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                Vector::from(aces(v.x), aces(v.y), aces(v.z))
+            }
+        };
+        return Vector::from(
+            mapped.x.clamp(0.0, 1.0),
+            mapped.y.clamp(0.0, 1.0),
+            mapped.z.clamp(0.0, 1.0),
+        );
+    }
+}
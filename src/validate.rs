@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+
+use crate::{
+    light::LightShape, mesh::Mesh, Environment, ReflectType, SceneData, SceneObject, Vector,
+};
+
+/// Something in a scene that will likely render wrong
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// Index of the object, or None for the camera and scene settings
+    pub object: Option<usize>,
+    /// Short identifier for scripts, like `degenerate-triangles`
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Checks a scene for invalid numbers, degenerate or inside-out meshes and
+/// materials that can't exist physically.
+pub fn validate(scene: &SceneData) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |object: Option<usize>, kind: &'static str, message: String| {
+        problems.push(Problem {
+            object,
+            kind,
+            message,
+        })
+    };
+
+    let camera = &scene.camera;
+    if !is_finite(camera.position) || !is_finite(camera.direction) {
+        report(
+            None,
+            "invalid-number",
+            "Camera position or direction is not finite".to_owned(),
+        );
+    } else if camera.direction.magnitude() == 0.0 {
+        report(
+            None,
+            "invalid-camera",
+            "Camera direction is zero".to_owned(),
+        );
+    }
+    if !is_positive(camera.focal_length) {
+        report(
+            None,
+            "invalid-camera",
+            format!("Focal length {} is not positive", camera.focal_length),
+        );
+    }
+    let settings = &scene.render_settings;
+    for (name, value) in [
+        ("Samples per pixel", settings.samples_per_pixel),
+        ("Resolution", settings.resolution_y),
+    ] {
+        if value == Some(0) {
+            report(None, "invalid-number", format!("{} is zero", name));
+        }
+    }
+    if let Some(max) = settings.firefly_clamp {
+        if !is_positive(max) {
+            report(
+                None,
+                "invalid-number",
+                format!("Firefly clamp {} is not positive", max),
+            );
+        }
+    }
+    let background = match scene.environment {
+        Environment::Color(color) => vec![color],
+        Environment::Gradient { bottom, top } => vec![bottom, top],
+        _ => Vec::new(),
+    };
+    if background
+        .iter()
+        .any(|&c| !is_finite(c) || c.x.min(c.y).min(c.z) < 0.0)
+    {
+        report(
+            None,
+            "invalid-color",
+            "Background color is negative or not finite".to_owned(),
+        );
+    }
+    for (i, shared) in scene.materials.iter().enumerate() {
+        if scene.materials[..i].iter().any(|m| m.name == shared.name) {
+            report(
+                None,
+                "duplicate-name",
+                format!("Material \"{}\" is defined twice", shared.name),
+            );
+        }
+        for (object, _) in &shared.users {
+            if *object >= scene.objects.len() {
+                report(
+                    None,
+                    "invalid-material",
+                    format!(
+                        "Material \"{}\" is used by object {}, which doesn't exist",
+                        shared.name, object
+                    ),
+                );
+            }
+        }
+    }
+
+    for (i, light) in scene.lights.iter().enumerate() {
+        let label = light.name.clone().unwrap_or_else(|| i.to_string());
+        let emission = light.emission;
+        if !is_finite(light.position) || !is_finite(emission) {
+            report(
+                None,
+                "invalid-number",
+                format!("Light {}: position or emission is not finite", label),
+            );
+        } else if emission.x.min(emission.y).min(emission.z) < 0.0 {
+            report(
+                None,
+                "invalid-color",
+                format!("Light {}: emission has negative components", label),
+            );
+        }
+        match light.shape {
+            LightShape::Point => {}
+            LightShape::Spot {
+                direction,
+                cone_angle,
+                falloff,
+            } => {
+                if !is_finite(direction) || direction.magnitude() == 0.0 {
+                    report(
+                        None,
+                        "invalid-number",
+                        format!("Light {}: direction is zero or not finite", label),
+                    );
+                }
+                let valid = is_positive(cone_angle) && cone_angle <= 180.0 && falloff >= 0.0;
+                if !valid {
+                    report(
+                        None,
+                        "invalid-number",
+                        format!(
+                            "Light {}: cone angle {} is not in (0, 180] or falloff {} is negative",
+                            label, cone_angle, falloff
+                        ),
+                    );
+                }
+            }
+            LightShape::Area { u, v } => {
+                if !is_finite(u) || !is_finite(v) || u.cross(&v).magnitude() == 0.0 {
+                    report(
+                        None,
+                        "invalid-number",
+                        format!("Light {}: area is zero or not finite", label),
+                    );
+                }
+            }
+        }
+    }
+
+    for (i, object) in scene.objects.iter().enumerate() {
+        if let Some(name) = &object.name {
+            if scene.objects[..i]
+                .iter()
+                .any(|o| o.name.as_ref() == Some(name))
+            {
+                report(
+                    Some(i),
+                    "duplicate-name",
+                    format!("Name \"{}\" is already used by an earlier object", name),
+                );
+            }
+        }
+
+        let i = Some(i);
+        if !is_finite(object.position) {
+            report(i, "invalid-number", "Position is not finite".to_owned());
+        }
+
+        match &object.type_ {
+            SceneObject::Sphere { radius } | SceneObject::Disc { radius, .. }
+                if !is_positive(*radius) =>
+            {
+                report(
+                    i,
+                    "invalid-number",
+                    format!("Radius {} is not positive", radius),
+                );
+            }
+            _ => {}
+        }
+        match &object.type_ {
+            SceneObject::Plane { normal } | SceneObject::Disc { normal, .. }
+                if !is_finite(*normal) || normal.magnitude() == 0.0 =>
+            {
+                report(
+                    i,
+                    "invalid-number",
+                    "Normal is zero or not finite".to_owned(),
+                );
+            }
+            _ => {}
+        }
+
+        if let SceneObject::Mesh(mesh) = &object.type_ {
+            let triangles = (0..mesh.triangle_count()).map(|t| mesh.triangle(t));
+            let invalid = triangles
+                .clone()
+                .filter(|tri| !(is_finite(tri.a) && is_finite(tri.b) && is_finite(tri.c)))
+                .count();
+            if invalid > 0 {
+                report(
+                    i,
+                    "invalid-number",
+                    format!("{} triangles have non-finite vertices", invalid),
+                );
+            }
+            let degenerate = triangles.filter(|tri| tri.area() == 0.0).count();
+            if degenerate > 0 {
+                report(
+                    i,
+                    "degenerate-triangles",
+                    format!("{} triangles have zero area", degenerate),
+                );
+            }
+            if is_closed(mesh) && signed_volume(mesh) < 0.0 {
+                report(
+                    i,
+                    "inverted-normals",
+                    "Closed mesh has normals pointing inwards".to_owned(),
+                );
+            }
+        }
+
+        let material = &object.material;
+        if !is_finite(material.color) || !is_finite(material.emmission) {
+            report(
+                i,
+                "invalid-color",
+                "Color or emission is not finite".to_owned(),
+            );
+            continue;
+        }
+        let color = material.color;
+        if color.x < 0.0 || color.y < 0.0 || color.z < 0.0 {
+            report(
+                i,
+                "invalid-color",
+                "Color has negative components".to_owned(),
+            );
+        } else if color.x.max(color.y).max(color.z) > 1.0 {
+            report(
+                i,
+                "invalid-color",
+                "Color above 1 reflects more light than arrives".to_owned(),
+            );
+        }
+        let emission = material.emmission;
+        if emission.x < 0.0 || emission.y < 0.0 || emission.z < 0.0 {
+            report(
+                i,
+                "invalid-color",
+                "Emission has negative components".to_owned(),
+            );
+        }
+        if let Some(texture) = &material.texture {
+            if !is_positive(texture.scale) {
+                report(
+                    i,
+                    "invalid-number",
+                    format!("Texture scale {} is not positive", texture.scale),
+                );
+            }
+            let color = texture.color;
+            if !is_finite(color) || color.x.min(color.y).min(color.z) < 0.0 {
+                report(
+                    i,
+                    "invalid-color",
+                    "Texture color is negative or not finite".to_owned(),
+                );
+            } else if color.x.max(color.y).max(color.z) > 1.0 {
+                report(
+                    i,
+                    "invalid-color",
+                    "Texture color above 1 reflects more light than arrives".to_owned(),
+                );
+            }
+        }
+        if let ReflectType::Refract { ior, absorption } = material.reflect_type {
+            if !is_positive(ior) {
+                report(
+                    i,
+                    "invalid-material",
+                    format!("Index of refraction {} is not positive", ior),
+                );
+            }
+            if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+                report(
+                    i,
+                    "invalid-material",
+                    "Absorption has negative components".to_owned(),
+                );
+            }
+        }
+    }
+    return problems;
+}
+
+/// Like `validate`, but for a scene that may have failed to load. A mesh or
+/// environment map that can't be read is a `missing-file` or `invalid-file`.
+pub fn validate_loaded(scene: &Result<SceneData, std::io::Error>) -> Vec<Problem> {
+    let error = match scene {
+        Ok(scene) => return validate(scene),
+        Err(error) => error,
+    };
+    return vec![Problem {
+        object: None,
+        kind: match error.kind() {
+            std::io::ErrorKind::NotFound => "missing-file",
+            _ => "invalid-file",
+        },
+        message: error.to_string(),
+    }];
+}
+
+/// False for NaN as well
+fn is_positive(x: f64) -> bool {
+    return x > 0.0 && x.is_finite();
+}
+
+fn is_finite(v: Vector) -> bool {
+    return v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+}
+
+/// True if every edge is shared by exactly two triangles
+fn is_closed(mesh: &Mesh) -> bool {
+    let key = |v: Vector| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+    let mut edges: HashMap<_, usize> = HashMap::new();
+    for t in 0..mesh.triangle_count() {
+        let tri = mesh.triangle(t);
+        for (a, b) in [(tri.a, tri.b), (tri.b, tri.c), (tri.c, tri.a)] {
+            let (a, b) = (key(a), key(b));
+            *edges
+                .entry(if a < b { (a, b) } else { (b, a) })
+                .or_default() += 1;
+        }
+    }
+    return mesh.triangle_count() > 0 && edges.values().all(|count| *count == 2);
+}
+
+/// Volume enclosed by a closed mesh, negative if its normals point inwards
+fn signed_volume(mesh: &Mesh) -> f64 {
+    return (0..mesh.triangle_count())
+        .map(|t| {
+            let tri = mesh.triangle(t);
+            tri.a.dot(&tri.b.cross(&tri.c)) / 6.0
+        })
+        .sum();
+}
+
+/// Problems of several scenes as a JSON array of
+/// `{"scene": .., "problems": [{"object": .., "kind": .., "message": ..}]}`
+pub fn to_json(results: &[(&str, Vec<Problem>)]) -> String {
+    let scenes = results
+        .iter()
+        .map(|(scene, problems)| {
+            let problems = problems
+                .iter()
+                .map(|problem| {
+                    format!(
+                        "{{\"object\": {}, \"kind\": {}, \"message\": {}}}",
+                        problem.object.map_or("null".to_owned(), |i| i.to_string()),
+                        json_string(problem.kind),
+                        json_string(&problem.message)
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "  {{\"scene\": {}, \"problems\": [{}]}}",
+                json_string(scene),
+                problems.join(", ")
+            )
+        })
+        .collect::<Vec<_>>();
+    return format!("[\n{}\n]", scenes.join(",\n"));
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
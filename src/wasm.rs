@@ -0,0 +1,101 @@
+//! Browser API, built with the `wasm` feature for `wasm32-unknown-unknown`.
+//! There is no thread pool in the browser, so build it without the default
+//! `parallel` feature, e.g. with `cargo check-wasm`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    mesh_file::MeshFile, render_with_alpha, scenes::load_scenes_each, Material, ReflectType,
+    RenderConfig, SceneId, SceneObject, SceneObjectData, Vector, Visibility,
+};
+
+/// Rendered image as straight RGBA bytes, row by row from the top, like the
+/// data of a canvas `ImageData`
+#[wasm_bindgen]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    rgba: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Image {
+    pub fn rgba(&self) -> Vec<u8> {
+        return self.rgba.clone();
+    }
+}
+
+/// Renders a builtin scene like the command line `args` without the program
+/// name, e.g. `["16", "120", "cornell", "--tonemap=aces"]`. Scenes that load
+/// mesh or environment map files fail, as there is no file system.
+#[wasm_bindgen]
+pub fn render(args: Vec<String>) -> Result<Image, JsError> {
+    return render_scene(args, None);
+}
+
+/// Like `render`, but also adds the mesh in `data` to the scene, at the
+/// origin and named after `file_name`, whose extension picks the format.
+#[wasm_bindgen]
+pub fn render_mesh(args: Vec<String>, file_name: &str, data: &[u8]) -> Result<Image, JsError> {
+    let mesh = MeshFile::new(file_name).parse(data)?;
+    return render_scene(
+        args,
+        Some(SceneObjectData {
+            type_: SceneObject::Mesh(mesh),
+            position: Vector::zero(),
+            material: Material {
+                color: Vector::uniform(0.75),
+                emmission: Vector::zero(),
+                reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
+            },
+            visibility: Visibility::Visible,
+            name: Some(file_name.to_owned()),
+        }),
+    );
+}
+
+fn render_scene(args: Vec<String>, mesh: Option<SceneObjectData>) -> Result<Image, JsError> {
+    let args: Vec<String> = std::iter::once("path-tracer".to_owned())
+        .chain(args)
+        .collect();
+    let invalid_args = || JsError::new("Invalid arguments");
+    let scenes = load_scenes_each();
+    let scene_id = RenderConfig::from(args.clone())
+        .ok_or_else(invalid_args)?
+        .scene_id;
+    // Selected like `RenderConfig::with_scene`, but other scenes may have failed to load
+    let (id, scene) = match scene_id {
+        SceneId::Int(i) => scenes.get(i),
+        SceneId::String(s) => scenes.iter().find(|(id, _)| *id == s),
+    }
+    .ok_or_else(|| JsError::new("Unknown scene"))?;
+    let mut scene = scene
+        .as_ref()
+        .map_err(|e| JsError::new(&format!("Scene {} can't be loaded: {}", id, e)))?
+        .clone();
+    let config =
+        RenderConfig::with_scene_settings(args, &scene.render_settings).ok_or_else(invalid_args)?;
+    scene.objects.extend(mesh);
+    scene
+        .apply_render_config(&config)
+        .map_err(|message| JsError::new(&message))?;
+
+    let (pixels, alpha) = render_with_alpha(&scene, &config);
+    let mut rgba = Vec::with_capacity(pixels.len() * 4);
+    // Pixels start at the bottom right
+    for (pixel, alpha) in pixels.iter().zip(&alpha).rev() {
+        let [r, g, b] = if *alpha > 0.0 {
+            config.display(*pixel / *alpha)
+        } else {
+            [0; 3]
+        };
+        rgba.extend_from_slice(&[r, g, b, (255.0 * alpha.clamp(0.0, 1.0) + 0.5) as u8]);
+    }
+    return Ok(Image {
+        width: config.resolution_x(),
+        height: config.resolution_y,
+        rgba,
+    });
+}
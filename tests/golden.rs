@@ -0,0 +1,82 @@
+//! Renders every builtin scene with a fixed seed and compares it against the
+//! reference images in tests/golden. Run with `UPDATE_GOLDEN=1` to rewrite
+//! the references after an intended change to the rendered output.
+
+#![allow(clippy::needless_return)]
+
+use std::time::Duration;
+
+use path_tracer::{render, scenes::load_scenes, write_ppm, RenderConfig, SceneId};
+
+/// Root mean square error, on a scale of 0 to 1, above which an image counts as changed
+const MAX_RMSE: f64 = 0.02;
+
+/// Width, height and channel values of a plain (P3) PPM image
+fn read_ppm(path: &str) -> (usize, usize, Vec<f64>) {
+    let content = std::fs::read_to_string(path).unwrap();
+    let mut tokens = content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace);
+    assert_eq!(tokens.next(), Some("P3"));
+    let mut next_number = || tokens.next().unwrap().parse::<usize>().unwrap();
+    let (width, height, max) = (next_number(), next_number(), next_number());
+    let values = (0..width * height * 3)
+        .map(|_| next_number() as f64 / max as f64)
+        .collect();
+    return (width, height, values);
+}
+
+#[test]
+fn golden_images() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    std::fs::create_dir_all("tests/golden").unwrap();
+    let mut failures = Vec::new();
+
+    for scene in load_scenes().unwrap() {
+        let config = RenderConfig {
+            samples_per_pixel: 8,
+            resolution_y: 24,
+            scene_id: SceneId::String(scene.id.clone()),
+            quiet: true,
+            seed: Some(1),
+            ..RenderConfig::default()
+        };
+        let pixels = render(&scene, &config);
+
+        let reference = format!("tests/golden/{}.ppm", scene.id);
+        if update {
+            write_ppm(&reference, &pixels, &config, Duration::ZERO).unwrap();
+            continue;
+        }
+        let rendered = std::env::temp_dir().join(format!("path-tracer-golden-{}.ppm", scene.id));
+        let rendered = rendered.to_str().unwrap();
+        write_ppm(rendered, &pixels, &config, Duration::ZERO).unwrap();
+
+        let (width, height, expected) = read_ppm(&reference);
+        let (actual_width, actual_height, actual) = read_ppm(rendered);
+        std::fs::remove_file(rendered).unwrap();
+        assert_eq!(
+            (actual_width, actual_height),
+            (width, height),
+            "{}",
+            scene.id
+        );
+
+        let squared_error = expected
+            .iter()
+            .zip(actual.iter())
+            .map(|(e, a)| (e - a).powi(2))
+            .sum::<f64>();
+        let rmse = (squared_error / expected.len() as f64).sqrt();
+        if rmse > MAX_RMSE {
+            failures.push(format!("{}: RMSE {:.4}", scene.id, rmse));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Images changed: {}",
+        failures.join(", ")
+    );
+}
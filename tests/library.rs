@@ -0,0 +1,51 @@
+use path_tracer::{
+    animation::Animation, render, CameraData, Environment, Material, ReflectType, RenderConfig,
+    RenderSettings, SceneData, SceneObject, SceneObjectData, Vector, Visibility,
+};
+
+#[test]
+fn render_custom_scene() {
+    let scene = SceneData {
+        id: "glowing-sphere".to_owned(),
+        objects: vec![SceneObjectData {
+            type_: SceneObject::Sphere { radius: 1.0 },
+            position: Vector::from(0.0, 0.0, -5.0),
+            material: Material {
+                color: Vector::zero(),
+                emmission: Vector::uniform(1.0),
+                reflect_type: ReflectType::Diffuse,
+                vertex_colors: false,
+                texture: None,
+            },
+            visibility: Visibility::Visible,
+            name: None,
+        }],
+        camera: CameraData {
+            position: Vector::zero(),
+            direction: Vector::from(0.0, 0.0, -1.0),
+            focal_length: 0.035,
+        },
+        camera_bookmarks: Vec::new(),
+        animation: Animation::default(),
+        environment: Environment::Black,
+        clip_plane: None,
+        render_settings: RenderSettings::default(),
+        materials: Vec::new(),
+        lights: Vec::new(),
+        caustics: None,
+        medium: None,
+    };
+    let config = RenderConfig {
+        samples_per_pixel: 4,
+        resolution_y: 8,
+        quiet: true,
+        ..RenderConfig::default()
+    };
+
+    let pixels = render(&scene, &config);
+
+    assert_eq!(pixels.len(), 12 * 8);
+    // The sphere fills the center of the image
+    let center = pixels[4 * 12 + 6];
+    assert!((center.x - 1.0).abs() < 1e-9, "{:?}", center);
+}
@@ -0,0 +1,121 @@
+//! Exports a scene to pbrt and, if a `pbrt` binary is installed, renders it
+//! there as well and compares the images. This catches systematic errors in
+//! our sampling and material code rather than regressions against ourselves.
+
+#![allow(clippy::needless_return)]
+
+use path_tracer::{
+    export_pbrt::export_pbrt, render, scenes::load_scenes, RenderConfig, SceneId, Vector,
+};
+
+/// Reads a little-endian RGB .pfm image, returning rows from top to bottom.
+fn read_pfm(path: &std::path::Path) -> (usize, usize, Vec<Vector>) {
+    let bytes = std::fs::read(path).unwrap();
+    let mut header_end = 0;
+    let mut header = Vec::new();
+    while header.len() < 3 {
+        let line_end = header_end
+            + bytes[header_end..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .unwrap();
+        header.push(String::from_utf8_lossy(&bytes[header_end..line_end]).to_string());
+        header_end = line_end + 1;
+    }
+    assert_eq!(header[0], "PF");
+    let size: Vec<usize> = header[1]
+        .split_whitespace()
+        .map(|s| s.parse().unwrap())
+        .collect();
+    assert!(
+        header[2].parse::<f64>().unwrap() < 0.0,
+        "expected little-endian pfm"
+    );
+    let (width, height) = (size[0], size[1]);
+
+    let floats: Vec<f64> = bytes[header_end..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+        .collect();
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in (0..height).rev() {
+        for col in 0..width {
+            let i = (row * width + col) * 3;
+            pixels.push(Vector::from(floats[i], floats[i + 1], floats[i + 2]));
+        }
+    }
+    return (width, height, pixels);
+}
+
+#[test]
+fn pbrt_reference_render() {
+    let scene = load_scenes()
+        .unwrap()
+        .into_iter()
+        .find(|scene| scene.id == "cornell")
+        .unwrap();
+    let render_config = RenderConfig {
+        samples_per_pixel: 256,
+        resolution_y: 20,
+        scene_id: SceneId::String(scene.id.clone()),
+        ..RenderConfig::default()
+    };
+
+    let pbrt_scene = export_pbrt(&scene, &render_config, "reference.pfm");
+    assert_eq!(
+        pbrt_scene.matches("AttributeBegin").count(),
+        scene.objects.len()
+    );
+    assert_eq!(pbrt_scene.matches("AreaLightSource").count(), 1);
+
+    if std::process::Command::new("pbrt")
+        .arg("--help")
+        .output()
+        .is_err()
+    {
+        println!("pbrt not found, skipping reference comparison");
+        return;
+    }
+
+    let dir = std::env::temp_dir().join("path-tracer-pbrt-reference");
+    std::fs::create_dir_all(&dir).unwrap();
+    let scene_path = dir.join("scene.pbrt");
+    let image_path = dir.join("reference.pfm");
+    std::fs::write(&scene_path, pbrt_scene).unwrap();
+    let status = std::process::Command::new("pbrt")
+        .arg("--quiet")
+        .arg("--outfile")
+        .arg(&image_path)
+        .arg(&scene_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let (width, height, reference) = read_pfm(&image_path);
+    assert_eq!(
+        (width, height),
+        (render_config.resolution_x(), render_config.resolution_y)
+    );
+    // Our pixels start at the bottom right
+    let ours: Vec<Vector> = render(&scene, &render_config).into_iter().rev().collect();
+
+    let clamp = |v: Vector| {
+        Vector::from(
+            v.x.clamp(0.0, 1.0),
+            v.y.clamp(0.0, 1.0),
+            v.z.clamp(0.0, 1.0),
+        )
+    };
+    let mut squared_error = 0.0;
+    let (mut mean_ours, mut mean_reference) = (Vector::zero(), Vector::zero());
+    for (a, b) in ours.iter().zip(reference.iter()) {
+        let diff = clamp(*a) - clamp(*b);
+        squared_error += diff.dot(&diff) / 3.0;
+        mean_ours = mean_ours + clamp(*a);
+        mean_reference = mean_reference + clamp(*b);
+    }
+    let rmse = (squared_error / ours.len() as f64).sqrt();
+    let mean_diff = (mean_ours - mean_reference).magnitude() / ours.len() as f64;
+    assert!(rmse < 0.1, "rmse = {}", rmse);
+    assert!(mean_diff < 0.02, "mean difference = {}", mean_diff);
+}